@@ -8,6 +8,8 @@ int main() {
     // AD - Move
     // Space - Jump / Double jump
     // R - restart
+    // H - hint
+    // F1 - all controls
     update_game();
     $
     $
@@ -0,0 +1,258 @@
+use crate::language::parse;
+use lang_c::ast::*;
+use lang_c::span::{Node, Span};
+use serde::{Deserialize, Serialize};
+
+/// Game API functions that never return false
+const ALWAYS_TRUE_FUNCTIONS: &[&str] = &["game_is_running"];
+
+/// What kind of code fits into a `$` slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlotKind {
+    Expression,
+    Statement,
+}
+
+impl SlotKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Expression => "expression",
+            Self::Statement => "statement",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoopReport {
+    pub span: Span,
+    /// Whether the loop has a reachable exit: a condition that can become false, or a `break`, `return` or `goto`
+    pub terminates: bool,
+    /// Indices of the `$` slots that could make this loop terminate with the right token in them
+    pub exit_slots: Vec<usize>,
+}
+
+/// Finds every loop in the level code and checks if it can ever terminate.
+/// Empty slots are filled with placeholders, so code like `if ($) $` can still be analyzed.
+pub fn analyze(code: &str) -> Vec<LoopReport> {
+    let slots = code
+        .match_indices('$')
+        .map(|(offset, _)| offset)
+        .collect::<Vec<_>>();
+    let program = match parse(&fill_slots(code)) {
+        Result::Ok(program) => program,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut reports = Vec::new();
+    for object in &program.unit.0 {
+        if let ExternalDeclaration::FunctionDefinition(node) = &object.node {
+            visit(&node.node.statement, &slots, &mut reports);
+        }
+    }
+    reports
+}
+
+fn fill_slots(code: &str) -> String {
+    let mut filled = String::with_capacity(code.len());
+    for (index, char) in code.char_indices() {
+        if char == '$' {
+            filled.push(match slot_kind(code, index) {
+                SlotKind::Expression => '0',
+                SlotKind::Statement => ';',
+            });
+        } else {
+            filled.push(char);
+        }
+    }
+    filled
+}
+
+/// Infers what the slot at `offset` expects from the code before it.
+/// Slots inside parentheses or after an operator take expressions, everything else takes statements
+pub fn slot_kind(code: &str, offset: usize) -> SlotKind {
+    let mut depth = 0;
+    for line in code[..offset].split('\n') {
+        let line = line.split("//").next().unwrap_or_default();
+        for char in line.chars() {
+            match char {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => (),
+            }
+        }
+    }
+    if depth > 0 {
+        return SlotKind::Expression;
+    }
+
+    let previous = code[..offset]
+        .chars()
+        .rev()
+        .find(|char| !char.is_whitespace() && *char != '$');
+    match previous {
+        Some('!' | '&' | '|' | '=' | '<' | '>' | '+' | '-' | '*' | '/' | ',') => {
+            SlotKind::Expression
+        }
+        _ => SlotKind::Statement,
+    }
+}
+
+fn visit(statement: &Node<Statement>, slots: &[usize], reports: &mut Vec<LoopReport>) {
+    let (condition, body) = match &statement.node {
+        Statement::Compound(items) => {
+            for item in items {
+                if let BlockItem::Statement(statement) = &item.node {
+                    visit(statement, slots, reports);
+                }
+            }
+            return;
+        }
+        Statement::If(node) => {
+            visit(&node.node.then_statement, slots, reports);
+            if let Some(else_statement) = &node.node.else_statement {
+                visit(else_statement, slots, reports);
+            }
+            return;
+        }
+        Statement::Labeled(node) => return visit(&node.node.statement, slots, reports),
+        Statement::Switch(node) => return visit(&node.node.statement, slots, reports),
+        Statement::While(node) => (Some(&node.node.expression), &node.node.statement),
+        Statement::DoWhile(node) => (Some(&node.node.expression), &node.node.statement),
+        Statement::For(node) => (node.node.condition.as_ref(), &node.node.statement),
+        _ => return,
+    };
+
+    let endless = condition.map_or(true, |condition| always_true(&condition.node));
+    let mut nested = Vec::new();
+    nested_loops(body, &mut nested);
+    reports.push(LoopReport {
+        span: statement.span,
+        terminates: !endless || has_exit(&body.node, false),
+        exit_slots: slots
+            .iter()
+            .enumerate()
+            .filter(|(_, offset)| {
+                contains(statement.span, **offset)
+                    && !nested.iter().any(|&span| contains(span, **offset))
+            })
+            .map(|(index, _)| index)
+            .collect(),
+    });
+    visit(body, slots, reports);
+}
+
+fn always_true(expression: &Expression) -> bool {
+    match expression {
+        Expression::Constant(constant) => match &constant.node {
+            Constant::Integer(integer) => integer.number.chars().any(|digit| digit != '0'),
+            _ => false,
+        },
+        Expression::Call(node) => {
+            if let Expression::Identifier(name) = &node.node.callee.node {
+                ALWAYS_TRUE_FUNCTIONS.contains(&name.node.name.as_str())
+            } else {
+                false
+            }
+        }
+        _ => false,
+    }
+}
+
+/// `nested` is set inside inner loops and switches, where `break` doesn't leave the loop being checked
+fn has_exit(statement: &Statement, nested: bool) -> bool {
+    match statement {
+        Statement::Break => !nested,
+        Statement::Return(_) | Statement::Goto(_) => true,
+        Statement::Compound(items) => items.iter().any(|item| match &item.node {
+            BlockItem::Statement(statement) => has_exit(&statement.node, nested),
+            _ => false,
+        }),
+        Statement::If(node) => {
+            has_exit(&node.node.then_statement.node, nested)
+                || node
+                    .node
+                    .else_statement
+                    .as_ref()
+                    .map_or(false, |statement| has_exit(&statement.node, nested))
+        }
+        Statement::Labeled(node) => has_exit(&node.node.statement.node, nested),
+        Statement::Switch(node) => has_exit(&node.node.statement.node, true),
+        Statement::While(node) => has_exit(&node.node.statement.node, true),
+        Statement::DoWhile(node) => has_exit(&node.node.statement.node, true),
+        Statement::For(node) => has_exit(&node.node.statement.node, true),
+        _ => false,
+    }
+}
+
+fn nested_loops(statement: &Node<Statement>, spans: &mut Vec<Span>) {
+    match &statement.node {
+        Statement::While(_) | Statement::DoWhile(_) | Statement::For(_) => {
+            spans.push(statement.span)
+        }
+        Statement::Compound(items) => {
+            for item in items {
+                if let BlockItem::Statement(statement) = &item.node {
+                    nested_loops(statement, spans);
+                }
+            }
+        }
+        Statement::If(node) => {
+            nested_loops(&node.node.then_statement, spans);
+            if let Some(else_statement) = &node.node.else_statement {
+                nested_loops(else_statement, spans);
+            }
+        }
+        Statement::Labeled(node) => nested_loops(&node.node.statement, spans),
+        Statement::Switch(node) => nested_loops(&node.node.statement, spans),
+        _ => (),
+    }
+}
+
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset < span.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVEL: &str =
+        "int main() {\n  while (game_is_running()) {\n    update_game();\n    $\n  }\n}";
+
+    #[test]
+    fn endless_loop_can_only_end_through_its_slots() {
+        let reports = analyze(LEVEL);
+        assert_eq!(reports.len(), 1);
+        assert!(!reports[0].terminates);
+        assert_eq!(reports[0].exit_slots, [0]);
+    }
+
+    #[test]
+    fn break_ends_the_loop() {
+        let reports = analyze(&LEVEL.replace('$', "if (player_is_jumping()) break;"));
+        assert!(reports[0].terminates);
+    }
+
+    #[test]
+    fn condition_that_can_become_false_ends_the_loop() {
+        let reports = analyze("int main() { int i = 0; while (i < 3) { i++; } }");
+        assert!(reports[0].terminates);
+    }
+
+    #[test]
+    fn break_only_leaves_the_innermost_loop() {
+        let reports = analyze("int main() { while (1) { for (;;) { break; } } }");
+        let terminates = reports
+            .iter()
+            .map(|report| report.terminates)
+            .collect::<Vec<_>>();
+        assert_eq!(terminates, [false, true]);
+    }
+
+    #[test]
+    fn slot_kind_follows_the_code_before_it() {
+        let code = "if ($) $";
+        assert_eq!(slot_kind(code, 4), SlotKind::Expression);
+        assert_eq!(slot_kind(code, 7), SlotKind::Statement);
+    }
+}
@@ -6,9 +6,52 @@ pub fn frandom(min: f32, max: f32) -> f32 {
     get_random_value::<i32>((min * 100.0) as i32, (max * 100.0) as i32) as f32 / 100.0
 }
 
+/// Glyph widths of raylib's default font for `' '..='~'`, at its base size of 10
+const DEFAULT_FONT_WIDTHS: [u8; 95] = [
+    3, 1, 4, 6, 5, 7, 6, 2, 3, 3, 5, 5, 2, 4, 1, 7, 5, 2, 5, 5, 5, 5, 5, 5, 5, 5, 1, 1, 3, 4, 3, 6,
+    7, 6, 6, 6, 6, 6, 6, 6, 6, 3, 5, 6, 5, 7, 6, 6, 6, 6, 6, 6, 7, 6, 7, 7, 6, 6, 6, 2, 7, 2, 3, 5,
+    2, 5, 5, 5, 5, 5, 4, 5, 5, 1, 2, 5, 2, 5, 5, 5, 5, 5, 5, 5, 4, 5, 5, 5, 5, 5, 5, 3, 1, 3, 4,
+];
+
+/// Width of text in the default font, what `measure_text` returns. Computed from the glyph widths,
+/// so the solver, which has no window to load the font, gets the same token hitboxes as the game
+pub fn text_width(text: &str, font_size: i32) -> i32 {
+    let font_size = font_size.max(10);
+    let spacing = font_size / 10;
+    text.split('\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let glyphs = line
+                .chars()
+                .map(|char| {
+                    let index = (char as usize).wrapping_sub(' ' as usize);
+                    // Missing glyphs are drawn as `?`
+                    DEFAULT_FONT_WIDTHS
+                        .get(index)
+                        .map_or(6, |&width| width as i32)
+                })
+                .sum::<i32>();
+            let spacings = (line.chars().count() as i32 - 1) * spacing;
+            (glyphs as f32 * font_size as f32 / 10.0 + spacings as f32) as i32
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Sounds requested by game logic, played by the main loop so levels can run without an audio device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEffect {
+    Jump,
+    PickToken,
+    TokenPlaced,
+    Death,
+}
+
 pub struct Assets {
     pub title_screen_texture: Texture2D,
     pub play_button_texture: Texture2D,
+    /// Ground tiles in a 4x4 grid, by which neighbours are ground too
+    pub tileset_texture: Texture2D,
 
     pub audio: RaylibAudio,
     pub play_button_hover_sound: Sound,
@@ -18,6 +61,7 @@ pub struct Assets {
     pub next_level_sound: Sound,
     pub pick_token_sound: Sound,
     pub token_placed_sound: Sound,
+    pub death_sound: Sound,
 }
 
 impl Assets {
@@ -29,6 +73,9 @@ impl Assets {
             play_button_texture: rl
                 .load_texture(thread, "Assets/PlayButton.png")
                 .map_err(|err| anyhow!(err))?,
+            tileset_texture: rl
+                .load_texture(thread, "Assets/Tileset.png")
+                .map_err(|err| anyhow!(err))?,
 
             audio: RaylibAudio::init_audio_device(),
             play_button_hover_sound: Sound::load_sound("Assets/PlayButtonHover.wav")
@@ -44,6 +91,17 @@ impl Assets {
                 .map_err(|err| anyhow!(err))?,
             token_placed_sound: Sound::load_sound("Assets/TokenPlaced.wav")
                 .map_err(|err| anyhow!(err))?,
+            death_sound: Sound::load_sound("Assets/Death.wav").map_err(|err| anyhow!(err))?,
         })
     }
+
+    pub fn play(&mut self, sound: SoundEffect) {
+        let sound = match sound {
+            SoundEffect::Jump => &self.jump_sound,
+            SoundEffect::PickToken => &self.pick_token_sound,
+            SoundEffect::TokenPlaced => &self.token_placed_sound,
+            SoundEffect::Death => &self.death_sound,
+        };
+        self.audio.play_sound(sound);
+    }
 }
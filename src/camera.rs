@@ -0,0 +1,59 @@
+use crate::assets::*;
+
+pub const SCREEN_SIZE: (f32, f32) = (800.0, 800.0);
+
+/// Follows the player smoothly once they leave a dead zone around the screen center, never showing
+/// anything outside the world. Part of the level state, so headless runs scroll the same way
+#[derive(Clone, Debug)]
+pub struct FollowCamera {
+    /// World position of the top-left corner of the screen
+    view: Vector2,
+}
+
+impl FollowCamera {
+    /// Half size of the box around the screen center the player can move in without scrolling
+    const DEAD_ZONE: (f32, f32) = (100.0, 80.0);
+    /// Seconds to cover half the distance to where the camera wants to be
+    const HALF_LIFE: f32 = 0.15;
+
+    /// Camera centered on `focus`
+    pub fn new(focus: Rectangle, world_size: Vector2) -> Self {
+        let center = rvec2(focus.x + focus.width / 2.0, focus.y + focus.height / 2.0);
+        let mut camera = Self {
+            view: center - rvec2(SCREEN_SIZE.0, SCREEN_SIZE.1) / 2.0,
+        };
+        camera.clamp(world_size);
+        camera
+    }
+
+    pub fn update(&mut self, frame_time: f32, focus: Rectangle, world_size: Vector2) {
+        let center = rvec2(focus.x + focus.width / 2.0, focus.y + focus.height / 2.0);
+        let offset = center - self.view - rvec2(SCREEN_SIZE.0, SCREEN_SIZE.1) / 2.0;
+        let outside = |offset: f32, dead_zone: f32| offset - offset.clamp(-dead_zone, dead_zone);
+        let target = self.view
+            + rvec2(
+                outside(offset.x, Self::DEAD_ZONE.0),
+                outside(offset.y, Self::DEAD_ZONE.1),
+            );
+        self.view += (target - self.view) * (1.0 - 0.5_f32.powf(frame_time / Self::HALF_LIFE));
+        self.clamp(world_size);
+    }
+
+    fn clamp(&mut self, world_size: Vector2) {
+        self.view.x = self.view.x.min(world_size.x - SCREEN_SIZE.0).max(0.0);
+        self.view.y = self.view.y.min(world_size.y - SCREEN_SIZE.1).max(0.0);
+    }
+
+    pub fn view(&self) -> Vector2 {
+        self.view
+    }
+
+    pub fn camera_2d(&self) -> Camera2D {
+        Camera2D {
+            offset: Vector2::zero(),
+            target: rvec2(self.view.x.round(), self.view.y.round()),
+            rotation: 0.0,
+            zoom: 1.0,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::assets::*;
+use crate::diagnostics::*;
+use crate::language::CompilationStatus;
+
+#[derive(Clone, Debug)]
+pub enum ConsoleLine {
+    Text(String, Color),
+    /// Points at a column of the source line above it
+    Caret {
+        line: String,
+        column: usize,
+    },
+}
+
+/// Fake terminal, imitating a toolchain building and running the level code
+#[derive(Clone, Debug)]
+pub struct Console {
+    compiler_output: Vec<ConsoleLine>,
+    stdout: Vec<String>,
+    pub diagnostics: Diagnostics,
+    pub expanded: bool,
+    header_rect: Option<Rectangle>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            compiler_output: Vec::new(),
+            stdout: Vec::new(),
+            diagnostics: Diagnostics::default(),
+            expanded: true,
+            header_rect: None,
+        }
+    }
+}
+
+impl Console {
+    const MAX_STDOUT_LINES: usize = 100;
+
+    /// Restarts the console for a freshly compiled program
+    pub fn compiled(&mut self, code: &str, status: &CompilationStatus) {
+        self.stdout.clear();
+        self.diagnostics.clear();
+        self.compiler_output = vec![ConsoleLine::Text(
+            "$ gcc -o game main.c".to_owned(),
+            Color::DARKGRAY,
+        )];
+        match status {
+            CompilationStatus::Success(_) => self
+                .compiler_output
+                .push(ConsoleLine::Text("$ ./game".to_owned(), Color::DARKGRAY)),
+            CompilationStatus::Failure(error) => {
+                let (line, column) = location(code, error.offset);
+                let source = code.lines().nth(line - 1).unwrap_or_default().to_owned();
+                self.compiler_output.push(ConsoleLine::Text(
+                    format!("main.c:{}:{}: error: {}", line, column, error.message),
+                    Color::RED,
+                ));
+                self.compiler_output
+                    .push(ConsoleLine::Text(source.clone(), Color::GRAY));
+                self.compiler_output.push(ConsoleLine::Caret {
+                    line: source,
+                    column: column - 1,
+                });
+            }
+            CompilationStatus::None => (),
+        }
+    }
+
+    pub fn error(&mut self, text: String) {
+        self.compiler_output
+            .push(ConsoleLine::Text(text, Color::RED));
+    }
+
+    /// Writes program output, like a real terminal would
+    pub fn print(&mut self, text: &str) {
+        let mut lines = text.split('\n');
+        if let Some(first) = lines.next() {
+            match self.stdout.last_mut() {
+                Some(last) => last.push_str(first),
+                None => self.stdout.push(first.to_owned()),
+            }
+        }
+        self.stdout.extend(lines.map(str::to_owned));
+        if self.stdout.len() > Self::MAX_STDOUT_LINES {
+            self.stdout
+                .drain(..self.stdout.len() - Self::MAX_STDOUT_LINES);
+        }
+    }
+
+    /// Drops program output and warnings, keeping the compiler output
+    pub fn clear_output(&mut self) {
+        self.stdout.clear();
+        self.diagnostics.clear();
+    }
+
+    pub fn stdout(&self) -> &[String] {
+        &self.stdout
+    }
+
+    pub fn update(&mut self, rl: &RaylibHandle) {
+        let header_clicked = rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT)
+            && self.header_rect.map_or(false, |rect| {
+                rect.check_collision_point_rec(rl.get_mouse_position())
+            });
+        if header_clicked || rl.is_key_pressed(KeyboardKey::KEY_GRAVE) {
+            self.expanded = !self.expanded;
+        }
+    }
+
+    /// Draws the collapsible console panel, returns its height
+    pub fn draw<D: RaylibDraw>(&mut self, d: &mut D, code: &str, position: Vector2) -> f32 {
+        let font_size = 20;
+        let header = format!("[{}] Console", if self.expanded { '-' } else { '+' });
+        self.header_rect = Some(rrect(
+            position.x,
+            position.y,
+            measure_text(&header, font_size),
+            font_size,
+        ));
+        d.draw_text(
+            &header,
+            position.x as _,
+            position.y as _,
+            font_size,
+            Color::DARKGRAY,
+        );
+        if !self.expanded {
+            return font_size as f32;
+        }
+
+        let mut lines = self.compiler_output.clone();
+        lines.extend(
+            self.diagnostics
+                .entries()
+                .iter()
+                .map(|entry| ConsoleLine::Text(entry.format(code), Color::ORANGE)),
+        );
+        lines.extend(
+            self.stdout
+                .iter()
+                .map(|line| ConsoleLine::Text(line.clone(), Color::BLACK)),
+        );
+
+        let max_lines = 10;
+        let mut y = position.y + font_size as f32;
+        let x = position.x as i32 + font_size;
+        for line in &lines[lines.len().saturating_sub(max_lines)..] {
+            match line {
+                ConsoleLine::Text(text, color) => d.draw_text(text, x, y as _, font_size, *color),
+                ConsoleLine::Caret { line, column } => {
+                    let prefix = line.chars().take(*column).collect::<String>();
+                    d.draw_text(
+                        "^",
+                        x + measure_text(&prefix, font_size),
+                        y as _,
+                        font_size,
+                        Color::RED,
+                    );
+                }
+            }
+            y += font_size as f32;
+        }
+        y - position.y
+    }
+}
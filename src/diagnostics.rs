@@ -0,0 +1,129 @@
+use lang_c::span::Span;
+
+/// Warning from running the level code, like an invalid operation the interpreter skipped
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    /// Times it happened at the same place
+    pub count: u32,
+}
+
+impl Diagnostic {
+    /// One line in the style of a compiler warning, `main.c:3:5: warning: ... (x2)`
+    pub fn format(&self, code: &str) -> String {
+        let mut text = String::from("main.c:");
+        if let Some(span) = self.span {
+            let (line, column) = location(code, span.start);
+            text += &format!("{}:{}:", line, column);
+        }
+        text += " warning: ";
+        text += &self.message;
+        if let Some(span) = self.span {
+            text += &format!(" `{}`", snippet(code, span));
+        }
+        if self.count > 1 {
+            text += &format!(" (x{})", self.count);
+        }
+        text
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Warnings with runtime values in them don't repeat, only the latest ones are kept
+    const MAX_ENTRIES: usize = 100;
+
+    /// Logs a warning. Repeated warnings at the same place are counted instead of duplicated,
+    /// so a loop running every frame doesn't flood the log
+    pub fn warn(&mut self, message: impl Into<String>, span: Option<Span>) {
+        let message = message.into();
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.message == message && entry.span == span)
+        {
+            entry.count += 1;
+        } else {
+            self.entries.push(Diagnostic {
+                message,
+                span,
+                count: 1,
+            });
+            if self.entries.len() > Self::MAX_ENTRIES {
+                self.entries.remove(0);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn entries(&self) -> &[Diagnostic] {
+        &self.entries
+    }
+}
+
+/// 1-based line and column of a byte offset in the code
+pub fn location(code: &str, offset: usize) -> (usize, usize) {
+    let before = &code[..offset.min(code.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// First line of the code a span covers
+pub fn snippet(code: &str, span: Span) -> &str {
+    let text = code.get(span.start..span.end).unwrap_or_default();
+    text.lines().next().unwrap_or_default().trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_warnings_are_counted() {
+        let mut diagnostics = Diagnostics::default();
+        let span = Some(Span { start: 4, end: 8 });
+        diagnostics.warn("undeclared identifier x", span);
+        diagnostics.warn("undeclared identifier x", span);
+        diagnostics.warn("undeclared identifier x", None);
+        let counts = diagnostics
+            .entries()
+            .iter()
+            .map(|entry| entry.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [2, 1]);
+    }
+
+    #[test]
+    fn only_the_latest_warnings_are_kept() {
+        let mut diagnostics = Diagnostics::default();
+        for value in 0..1000 {
+            diagnostics.warn(format!("format %s doesn't match argument {}", value), None);
+        }
+        let entries = diagnostics.entries();
+        assert_eq!(entries.len(), Diagnostics::MAX_ENTRIES);
+        assert_eq!(
+            entries.last().unwrap().message,
+            "format %s doesn't match argument 999"
+        );
+    }
+
+    #[test]
+    fn locations_are_one_based() {
+        assert_eq!(location("int a;\n  b = 1;", 9), (2, 3));
+        assert_eq!(
+            snippet("x = y +\n z;", Span { start: 0, end: 11 }),
+            "x = y +"
+        );
+    }
+}
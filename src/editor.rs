@@ -0,0 +1,599 @@
+use crate::assets::*;
+use crate::camera::SCREEN_SIZE;
+use crate::level::{EnemyBehavior, Level};
+use crate::level_file::*;
+use crate::player::Player;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tool {
+    Platform,
+    Token,
+    Enemy,
+    Spawn,
+}
+
+impl Tool {
+    const ALL: [(KeyboardKey, Tool); 4] = [
+        (KeyboardKey::KEY_ONE, Tool::Platform),
+        (KeyboardKey::KEY_TWO, Tool::Token),
+        (KeyboardKey::KEY_THREE, Tool::Enemy),
+        (KeyboardKey::KEY_FOUR, Tool::Spawn),
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Platform => "Platform",
+            Self::Token => "Token",
+            Self::Enemy => "Enemy",
+            Self::Spawn => "Spawn",
+        }
+    }
+}
+
+/// What keyboard input goes to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    World,
+    Code,
+    TokenText,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Drag {
+    /// Drawing a new platform from this corner
+    NewPlatform(Vector2),
+    MovePlatform(usize, Vector2),
+    ResizePlatform(usize),
+    MoveToken(usize, Vector2),
+    MoveEnemy(usize, Vector2),
+    MoveSpawn(Vector2),
+}
+
+/// Edits a level file and its code in place, saving writes the same format [`Level::load`] reads
+pub struct Editor {
+    index: usize,
+    path: PathBuf,
+    file: LevelFile,
+    code: String,
+    code_cursor: usize,
+    tool: Tool,
+    focus: Focus,
+    token_text: String,
+    drag: Option<Drag>,
+    message: String,
+    /// World position of the top-left corner of the screen
+    scroll: Vector2,
+}
+
+impl Editor {
+    const GRID: f32 = 5.0;
+    const HANDLE_SIZE: f32 = 10.0;
+    const ENEMY_SIZE: Vector2 = Vector2 { x: 60.0, y: 80.0 };
+
+    pub fn open(levels: &[PathBuf], index: usize) -> Result<Self> {
+        let path = levels.get(index).context("No such level")?.clone();
+        let file = LevelFile::load(&path)?;
+        let code = file.load_code(&path)?;
+        Ok(Self {
+            index,
+            message: format!("Editing {}", path.display()),
+            path,
+            file,
+            code_cursor: code.len(),
+            code,
+            tool: Tool::Platform,
+            focus: Focus::World,
+            token_text: "break;".to_owned(),
+            drag: None,
+            scroll: Vector2::zero(),
+        })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Runs the checks [`Level::load`] does, so what's saved or play-tested can be loaded
+    fn check(&self) -> Result<()> {
+        self.file.validate()?;
+        self.file.check_code(&self.code, &self.path)
+    }
+
+    /// Builds the level as it is in the editor, without saving it
+    pub fn play_test(&self) -> Result<(Level, Player)> {
+        self.check().context("Can't play-test")?;
+        Ok(Level::from_file(&self.file, self.code.clone(), self.index))
+    }
+
+    pub fn report_error(&mut self, error: &Error) {
+        self.message = format!("{:#}", error);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.check().context("Not saved")?;
+        let text =
+            ron::ser::to_string_pretty(&self.file, ron::ser::PrettyConfig::new().depth_limit(2))?;
+        std::fs::write(&self.path, text)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        let code_path = self.file.code_path(&self.path);
+        std::fs::write(&code_path, &self.code)
+            .with_context(|| format!("Failed to write {}", code_path.display()))?;
+        Ok(())
+    }
+
+    // * --------------------------------------------------------------------------------- Update --------------------------------------------------------------------------------- * //
+    pub fn update(&mut self, rl: &mut RaylibHandle) {
+        match self.focus {
+            Focus::Code => return self.edit_code(rl),
+            Focus::TokenText => {
+                while let Some(char) = rl.get_char_pressed() {
+                    self.token_text.push(char);
+                }
+                if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) {
+                    self.token_text.pop();
+                }
+                if rl.is_key_pressed(KeyboardKey::KEY_ENTER)
+                    || rl.is_key_pressed(KeyboardKey::KEY_TAB)
+                {
+                    // New tokens get this text, empty tokens break placing them into the code
+                    if self.token_text.trim().is_empty() {
+                        self.message = String::from("Token text can't be empty");
+                    } else {
+                        self.focus = Focus::World;
+                    }
+                }
+                return;
+            }
+            Focus::World => (),
+        }
+
+        let control = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        if control && rl.is_key_pressed(KeyboardKey::KEY_S) {
+            self.message = match self.save() {
+                Result::Ok(()) => format!("Saved {}", self.path.display()),
+                Err(err) => format!("{:#}", err),
+            };
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_TAB) {
+            self.focus = Focus::Code;
+        }
+        if self.tool == Tool::Token && rl.is_key_pressed(KeyboardKey::KEY_T) {
+            self.focus = Focus::TokenText;
+        }
+        for (key, tool) in Tool::ALL {
+            if rl.is_key_pressed(key) {
+                self.tool = tool;
+            }
+        }
+
+        if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_MIDDLE) {
+            let max_scroll = rvec2(
+                self.file.world_size.0 - SCREEN_SIZE.0,
+                self.file.world_size.1 - SCREEN_SIZE.1,
+            );
+            self.scroll -= rl.get_mouse_delta();
+            self.scroll.x = self.scroll.x.min(max_scroll.x).max(0.0);
+            self.scroll.y = self.scroll.y.min(max_scroll.y).max(0.0);
+        }
+
+        let mouse = rl.get_mouse_position() + self.scroll;
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            self.drag = Some(self.start_drag(mouse));
+        }
+        if let Some(drag) = self.drag {
+            if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
+                self.apply_drag(drag, mouse);
+            } else {
+                if let Drag::NewPlatform(start) = drag {
+                    let rect = Self::rect_between(start, snap(mouse));
+                    if rect.width >= Self::GRID && rect.height >= Self::GRID {
+                        self.file
+                            .platforms
+                            .push((rect.x, rect.y, rect.width, rect.height));
+                    }
+                }
+                self.drag = None;
+            }
+        }
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+            self.delete_at(mouse);
+        }
+    }
+
+    fn start_drag(&mut self, mouse: Vector2) -> Drag {
+        match self.tool {
+            Tool::Platform => match self.platform_at(mouse) {
+                Some(index) => {
+                    let (x, y, width, height) = self.file.platforms[index];
+                    let corner = rvec2(x + width, y + height);
+                    if (corner - mouse).x < Self::HANDLE_SIZE
+                        && (corner - mouse).y < Self::HANDLE_SIZE
+                    {
+                        Drag::ResizePlatform(index)
+                    } else {
+                        Drag::MovePlatform(index, mouse - rvec2(x, y))
+                    }
+                }
+                None => Drag::NewPlatform(snap(mouse)),
+            },
+            Tool::Token => match self.token_at(mouse) {
+                Some(index) => {
+                    let (x, y) = self.file.tokens[index].position;
+                    Drag::MoveToken(index, mouse - rvec2(x, y))
+                }
+                None => {
+                    let position = snap(mouse);
+                    self.file.tokens.push(TokenFile {
+                        text: self.token_text.clone(),
+                        position: (position.x, position.y),
+                        kind: None,
+                        template: None,
+                    });
+                    Drag::MoveToken(self.file.tokens.len() - 1, Vector2::zero())
+                }
+            },
+            Tool::Enemy => match self.enemy_at(mouse) {
+                Some(index) => {
+                    let (x, y) = self.file.enemies[index].position;
+                    Drag::MoveEnemy(index, mouse - rvec2(x, y))
+                }
+                None => {
+                    let position = snap(mouse);
+                    self.file.enemies.push(EnemyFile {
+                        position: (position.x, position.y),
+                        behavior: EnemyBehavior::default(),
+                    });
+                    Drag::MoveEnemy(self.file.enemies.len() - 1, Vector2::zero())
+                }
+            },
+            Tool::Spawn => Drag::MoveSpawn(self.player_size() / 2.0),
+        }
+    }
+
+    fn apply_drag(&mut self, drag: Drag, mouse: Vector2) {
+        match drag {
+            Drag::NewPlatform(_) => (),
+            Drag::MovePlatform(index, offset) => {
+                let position = snap(mouse - offset);
+                let platform = &mut self.file.platforms[index];
+                platform.0 = position.x;
+                platform.1 = position.y;
+            }
+            Drag::ResizePlatform(index) => {
+                let corner = snap(mouse);
+                let platform = &mut self.file.platforms[index];
+                platform.2 = (corner.x - platform.0).max(Self::GRID);
+                platform.3 = (corner.y - platform.1).max(Self::GRID);
+            }
+            Drag::MoveToken(index, offset) => {
+                let position = snap(mouse - offset);
+                self.file.tokens[index].position = (position.x, position.y);
+            }
+            Drag::MoveEnemy(index, offset) => {
+                let position = snap(mouse - offset);
+                self.file.enemies[index].position = (position.x, position.y);
+            }
+            Drag::MoveSpawn(offset) => {
+                let position = snap(mouse - offset);
+                self.file.spawn = (position.x, position.y);
+            }
+        }
+    }
+
+    fn delete_at(&mut self, mouse: Vector2) {
+        // The drag holds the index of what it moves
+        if self.drag.is_some() {
+            return;
+        }
+        match self.tool {
+            Tool::Platform => {
+                if let Some(index) = self.platform_at(mouse) {
+                    self.file.platforms.remove(index);
+                }
+            }
+            Tool::Token => {
+                if let Some(index) = self.token_at(mouse) {
+                    self.file.tokens.remove(index);
+                }
+            }
+            Tool::Enemy => {
+                if let Some(index) = self.enemy_at(mouse) {
+                    self.file.enemies.remove(index);
+                }
+            }
+            Tool::Spawn => (),
+        }
+    }
+
+    fn edit_code(&mut self, rl: &mut RaylibHandle) {
+        while let Some(char) = rl.get_char_pressed() {
+            self.code.insert(self.code_cursor, char);
+            self.code_cursor += char.len_utf8();
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
+            self.code.insert(self.code_cursor, '\n');
+            self.code_cursor += 1;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) {
+            if let Some(char) = self.code[..self.code_cursor].chars().next_back() {
+                self.code_cursor -= char.len_utf8();
+                self.code.remove(self.code_cursor);
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_LEFT) {
+            if let Some(char) = self.code[..self.code_cursor].chars().next_back() {
+                self.code_cursor -= char.len_utf8();
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_RIGHT) {
+            if let Some(char) = self.code[self.code_cursor..].chars().next() {
+                self.code_cursor += char.len_utf8();
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_UP) {
+            self.move_code_cursor_vertically(false);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_DOWN) {
+            self.move_code_cursor_vertically(true);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_TAB) {
+            self.focus = Focus::World;
+        }
+    }
+
+    fn move_code_cursor_vertically(&mut self, down: bool) {
+        let line_start = self.code[..self.code_cursor]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        let column = self.code[line_start..self.code_cursor].chars().count();
+        let target_start = if down {
+            match self.code[self.code_cursor..].find('\n') {
+                Some(index) => self.code_cursor + index + 1,
+                None => return,
+            }
+        } else {
+            if line_start == 0 {
+                return;
+            }
+            self.code[..line_start - 1]
+                .rfind('\n')
+                .map_or(0, |index| index + 1)
+        };
+        let target_line = self.code[target_start..]
+            .split('\n')
+            .next()
+            .unwrap_or_default();
+        self.code_cursor = target_start
+            + target_line
+                .char_indices()
+                .nth(column)
+                .map_or(target_line.len(), |(index, _)| index);
+    }
+
+    fn platform_at(&self, point: Vector2) -> Option<usize> {
+        self.file
+            .platforms
+            .iter()
+            .rposition(|&(x, y, width, height)| {
+                rrect(x, y, width, height).check_collision_point_rec(point)
+            })
+    }
+
+    fn token_at(&self, point: Vector2) -> Option<usize> {
+        self.file
+            .tokens
+            .iter()
+            .rposition(|token| Self::token_rect(token).check_collision_point_rec(point))
+    }
+
+    fn enemy_at(&self, point: Vector2) -> Option<usize> {
+        self.file
+            .enemies
+            .iter()
+            .rposition(|enemy| Self::enemy_rect(enemy).check_collision_point_rec(point))
+    }
+
+    fn token_rect(token: &TokenFile) -> Rectangle {
+        let font_size = 20;
+        let width = text_width(&token.text, font_size) as f32;
+        rrect(
+            token.position.0 - width / 2.0,
+            token.position.1 - font_size as f32,
+            width,
+            font_size,
+        )
+    }
+
+    fn enemy_rect(enemy: &EnemyFile) -> Rectangle {
+        rrect(
+            enemy.position.0,
+            enemy.position.1 - Self::ENEMY_SIZE.y,
+            Self::ENEMY_SIZE.x,
+            Self::ENEMY_SIZE.y,
+        )
+    }
+
+    fn player_size(&self) -> Vector2 {
+        rvec2(self.file.physics.size.0, self.file.physics.size.1)
+    }
+
+    fn rect_between(a: Vector2, b: Vector2) -> Rectangle {
+        rrect(
+            a.x.min(b.x),
+            a.y.min(b.y),
+            (a.x - b.x).abs(),
+            (a.y - b.y).abs(),
+        )
+    }
+
+    // * ---------------------------------------------------------------------------------- Draw ---------------------------------------------------------------------------------- * //
+    pub fn draw(&self, d: &mut RaylibDrawHandle, tileset: &Texture2D) {
+        d.clear_background(Color::RAYWHITE);
+        let font_size = 20;
+        let mouse = d.get_mouse_position() + self.scroll;
+
+        {
+            let mut d = d.begin_mode2D(Camera2D {
+                offset: Vector2::zero(),
+                target: self.scroll,
+                rotation: 0.0,
+                zoom: 1.0,
+            });
+            d.draw_rectangle_lines_ex(
+                rrect(0, 0, self.file.world_size.0, self.file.world_size.1),
+                1.0,
+                Color::LIGHTGRAY,
+            );
+            // The tile map is edited in the level file, it's only shown here
+            if let Some(tiles) = &self.file.tiles {
+                tiles.draw(&mut d, tileset);
+                for platform in tiles.platforms() {
+                    platform.draw(&mut d);
+                }
+            }
+            for &(x, y, width, height) in &self.file.platforms {
+                d.draw_rectangle_rec(rrect(x, y, width, height), Color::new(200, 200, 200, 255));
+                d.draw_rectangle_lines_ex(rrect(x, y, width, height), 1.0, Color::GRAY);
+                d.draw_rectangle_v(
+                    rvec2(x + width, y + height) - rvec2(Self::HANDLE_SIZE, Self::HANDLE_SIZE),
+                    rvec2(Self::HANDLE_SIZE, Self::HANDLE_SIZE),
+                    Color::GRAY,
+                );
+            }
+            for platform in &self.file.special_platforms {
+                platform.build().draw(&mut d);
+            }
+            if let Some(Drag::NewPlatform(start)) = self.drag {
+                let rect = Self::rect_between(start, snap(mouse));
+                d.draw_rectangle_lines_ex(rect, 1.0, Color::DARKGRAY);
+            }
+            for enemy in &self.file.enemies {
+                d.draw_rectangle_rec(Self::enemy_rect(enemy), enemy.behavior.color());
+                if let EnemyBehavior::FollowPath { points, .. } = &enemy.behavior {
+                    for (index, &(x, y)) in points.iter().enumerate() {
+                        let (next_x, next_y) = points[(index + 1) % points.len()];
+                        d.draw_line_v(rvec2(x, y), rvec2(next_x, next_y), Color::PINK);
+                    }
+                }
+            }
+            for token in &self.file.tokens {
+                let rect = Self::token_rect(token);
+                d.draw_text(&token.text, rect.x as _, rect.y as _, font_size, Color::RED);
+            }
+            let spawn = rvec2(self.file.spawn.0, self.file.spawn.1);
+            d.draw_rectangle_lines_ex(
+                rrect(spawn.x, spawn.y, self.player_size().x, self.player_size().y),
+                2.0,
+                Color::GRAY,
+            );
+            d.draw_text("spawn", spawn.x as _, spawn.y as i32 - 15, 15, Color::GRAY);
+        }
+
+        // * Code
+        let mut cursor = rvec2(12, 30);
+        let mut offset = 0;
+        for line in self.code.split('\n') {
+            d.draw_text(line, cursor.x as _, cursor.y as _, font_size, Color::GRAY);
+            let line_end = offset + line.len();
+            if self.focus == Focus::Code && (offset..=line_end).contains(&self.code_cursor) {
+                let advance = measure_text(&line[..self.code_cursor - offset], font_size);
+                d.draw_rectangle_v(
+                    cursor + rvec2(advance, 0),
+                    rvec2(2, font_size),
+                    Color::BLACK,
+                );
+            }
+            offset = line_end + 1;
+            cursor.y += font_size as f32;
+        }
+
+        // * HUD
+        let mut x = 10;
+        let y = 800 - font_size * 3 - 10;
+        for (index, (_, tool)) in Tool::ALL.iter().enumerate() {
+            let text = format!("[{}] {}", index + 1, tool.name());
+            let color = if *tool == self.tool {
+                Color::BLACK
+            } else {
+                Color::GRAY
+            };
+            d.draw_text(&text, x, y, font_size, color);
+            x += measure_text(&text, font_size) + 15;
+        }
+        let focus = match self.focus {
+            Focus::World => "Tab: edit code, Ctrl+S: save, F2: play-test, middle drag: scroll",
+            Focus::Code => "Editing code, Tab: done",
+            Focus::TokenText => "Editing token text, Enter: done",
+        };
+        d.draw_text(focus, x, y, font_size, Color::DARKGRAY);
+        if self.tool == Tool::Token {
+            d.draw_text(
+                &format!("Token (T to edit): {}", self.token_text),
+                10,
+                y + font_size,
+                font_size,
+                if self.focus == Focus::TokenText {
+                    Color::BLACK
+                } else {
+                    Color::GRAY
+                },
+            );
+        }
+        d.draw_text(
+            &self.message,
+            10,
+            y + font_size * 2,
+            font_size,
+            Color::DARKGRAY,
+        );
+    }
+}
+
+fn snap(point: Vector2) -> Vector2 {
+    rvec2(
+        (point.x / Editor::GRID).round() * Editor::GRID,
+        (point.y / Editor::GRID).round() * Editor::GRID,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor() -> Editor {
+        let file: LevelFile = ron::from_str(
+            "(code: \"code1.c\", spawn: (0, 0), platforms: [(0, 0, 100, 100), (200, 0, 100, 100)])",
+        )
+        .unwrap();
+        Editor {
+            index: 0,
+            path: PathBuf::from("levels/level1.ron"),
+            file,
+            code: String::new(),
+            code_cursor: 0,
+            tool: Tool::Platform,
+            focus: Focus::World,
+            token_text: "break;".to_owned(),
+            drag: None,
+            message: String::new(),
+            scroll: Vector2::zero(),
+        }
+    }
+
+    #[test]
+    fn deleting_while_dragging_keeps_the_dragged_item() {
+        let mut editor = editor();
+        let mouse = rvec2(250, 50);
+        editor.drag = Some(editor.start_drag(mouse));
+        editor.delete_at(mouse);
+        assert_eq!(editor.file.platforms.len(), 2);
+        editor.apply_drag(editor.drag.unwrap(), rvec2(260, 50));
+        assert_eq!(editor.file.platforms[1], (210.0, 0.0, 100.0, 100.0));
+
+        editor.drag = None;
+        editor.delete_at(mouse);
+        assert_eq!(editor.file.platforms.len(), 1);
+    }
+}
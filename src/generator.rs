@@ -0,0 +1,334 @@
+use crate::assets::*;
+use crate::camera::SCREEN_SIZE;
+use crate::level::Level;
+use crate::level_file::{LevelFile, TokenFile};
+use crate::player::{Physics, Player};
+use crate::solver::{Search, Solution};
+use crate::tilemap::TileMap;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+const TILE_SIZE: f32 = 40.0;
+/// Layouts tried for a seed before giving up, each one is checked with the solver
+const MAX_ATTEMPTS: usize = 8;
+
+/// Loop body with `$` slots, and the tokens it takes to break out of it
+struct Puzzle {
+    body: &'static str,
+    required: &'static [&'static str],
+}
+
+const PUZZLES: &[Puzzle] = &[
+    Puzzle {
+        body: "$\n    update_game();\n    $",
+        required: &["break;"],
+    },
+    Puzzle {
+        body: "update_game();\n    if ($) $",
+        required: &["player_is_jumping()", "break;"],
+    },
+    Puzzle {
+        body: "update_game();\n    $",
+        required: &["if", "player_is_jumping()", "break;"],
+    },
+];
+
+/// Tokens that fit the slots but don't get the player out of the loop
+const DECOYS: &[&str] = &[
+    "update_game();",
+    "load_next_asset();",
+    "start_game();",
+    "continue;",
+    "shrink_player();",
+];
+
+/// SplitMix64, small and good enough for level layouts
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        (self.next() % len as u64) as usize
+    }
+
+    fn range(&mut self, range: Range<usize>) -> usize {
+        range.start + self.index(range.len())
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        ((self.next() >> 40) as f32 / (1 << 24) as f32) < probability
+    }
+}
+
+/// A level made from a seed, with the proof it can be finished
+pub struct Generated {
+    pub file: LevelFile,
+    pub code: String,
+    pub solution: Solution,
+}
+
+/// Builds levels from a seed until the solver finishes one, a few solver states at a time so the game
+/// can keep running frames while it searches
+pub struct Generation {
+    seed: u64,
+    /// Level number it's for, numbered after the hand-made ones
+    index: usize,
+    rng: Rng,
+    attempts: usize,
+    /// Layout being solved
+    search: Option<(LevelFile, String, Search)>,
+    result: Option<Result<Generated>>,
+}
+
+impl Generation {
+    pub fn new(seed: u64, index: usize) -> Self {
+        Self {
+            seed,
+            index,
+            rng: Rng(seed),
+            attempts: 0,
+            search: None,
+            result: None,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn done(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Searches up to `states` solver states
+    pub fn advance(&mut self, states: usize) {
+        if self.done() {
+            return;
+        }
+        let (_, _, search) = self.search.get_or_insert_with(|| {
+            let (file, code) = layout(&mut self.rng, self.seed);
+            let (level, player) = Level::from_file(&file, code.clone(), 0);
+            let search = Search::new(&level, &player);
+            (file, code, search)
+        });
+        match search.advance(states) {
+            None => (),
+            Some(Some(solution)) => {
+                if let Some((file, code, _)) = self.search.take() {
+                    self.result = Some(Ok(Generated {
+                        file,
+                        code,
+                        solution,
+                    }));
+                }
+            }
+            Some(None) => {
+                self.search = None;
+                self.attempts += 1;
+                if self.attempts >= MAX_ATTEMPTS {
+                    self.result = Some(Err(anyhow!(
+                        "No solvable level in {} attempts with seed {}",
+                        MAX_ATTEMPTS,
+                        self.seed
+                    )));
+                }
+            }
+        }
+    }
+
+    /// The level, once [`Generation::done`]
+    pub fn finish(self) -> Option<Result<(Level, Player)>> {
+        let index = self.index;
+        let generated = self.result?;
+        Some(generated.map(|generated| Level::from_file(&generated.file, generated.code, index)))
+    }
+}
+
+/// Runs a whole [`Generation`]
+pub fn generate(seed: u64) -> Result<Generated> {
+    let mut generation = Generation::new(seed, 0);
+    loop {
+        generation.advance(usize::MAX);
+        if let Some(result) = generation.result.take() {
+            return result;
+        }
+    }
+}
+
+/// A staircase of pillars the player can climb with the default physics, the tokens that break the
+/// loop on the way up and the last one on top
+fn layout(rng: &mut Rng, seed: u64) -> (LevelFile, String) {
+    let puzzle = &PUZZLES[rng.index(PUZZLES.len())];
+    let code = format!(
+        "#include <game.h>
+
+int main() {{
+  start_game();
+  // Generated from seed {}
+  while (game_is_running()) {{
+    {}
+  }}
+  return 0;
+}}
+",
+        seed, puzzle.body
+    );
+
+    let physics = Physics::default();
+    let jump_height = physics.jump_velocity.powi(2) / (2.0 * physics.gravity);
+    let max_rise = ((jump_height * physics.max_jumps as f32 * 0.8) / TILE_SIZE).max(1.0) as usize;
+    let columns = (SCREEN_SIZE.0 / TILE_SIZE) as usize;
+    let rows = (SCREEN_SIZE.1 / TILE_SIZE) as usize;
+    // Room above the highest pillar for the player and a token
+    let max_height = rows - 6;
+
+    // Left column, width and height of every pillar, in tiles
+    let mut pillars = Vec::<(usize, usize, usize)>::new();
+    let mut column = rng.range(3..5);
+    let mut height = 0;
+    loop {
+        let width = rng.range(2..5);
+        if column + width > columns {
+            break;
+        }
+        height = if height > 2 && rng.chance(0.25) {
+            height - rng.range(1..3)
+        } else {
+            (height + rng.range(1..max_rise + 1)).min(max_height)
+        };
+        pillars.push((column, width, height));
+        column += width + rng.range(1..3);
+    }
+
+    let mirrored = rng.chance(0.5);
+    let mirror = |x: f32| {
+        if mirrored {
+            SCREEN_SIZE.0 - x
+        } else {
+            x
+        }
+    };
+    let mut tiles = vec![vec!['.'; columns]; rows];
+    for &(left, width, height) in &pillars {
+        for row in &mut tiles[rows - height..] {
+            row[left..left + width].fill('#');
+        }
+    }
+    if mirrored {
+        tiles.iter_mut().for_each(|row| row.reverse());
+    }
+
+    let on_pillar = |rng: &mut Rng, pillar: usize| {
+        let (left, width, height) = pillars[pillar];
+        let x = (left as f32 + 0.5 + rng.index(width) as f32) * TILE_SIZE;
+        (mirror(x), (rows - height) as f32 * TILE_SIZE)
+    };
+    let mut tokens = Vec::new();
+    let decoys = rng.range(1..4);
+    for index in 0..puzzle.required.len() + decoys {
+        let (text, pillar) = match puzzle.required.get(index) {
+            Some(&text) if index + 1 == puzzle.required.len() => (text, pillars.len() - 1),
+            Some(&text) => (text, rng.index(pillars.len())),
+            None => (DECOYS[rng.index(DECOYS.len())], rng.index(pillars.len())),
+        };
+        tokens.push(TokenFile {
+            text: text.to_owned(),
+            position: on_pillar(rng, pillar),
+            kind: None,
+            template: None,
+        });
+    }
+
+    let spawn_x = 10.0;
+    let file = LevelFile {
+        code: format!("generated_{}.c", seed),
+        spawn: (
+            mirror(spawn_x) - if mirrored { physics.size.0 } else { 0.0 },
+            SCREEN_SIZE.1 - physics.size.1 - 1.0,
+        ),
+        world_size: SCREEN_SIZE,
+        platforms: Vec::new(),
+        special_platforms: Vec::new(),
+        tiles: Some(TileMap {
+            tile_size: TILE_SIZE,
+            origin: (0.0, 0.0),
+            rows: tiles
+                .into_iter()
+                .map(|row| row.into_iter().collect())
+                .collect(),
+        }),
+        tokens,
+        enemies: Vec::new(),
+        globals: BTreeMap::new(),
+        slots: BTreeMap::new(),
+        friendly_enemies: false,
+        physics,
+    };
+    (file, code)
+}
+
+/// `--generate [seed]`: prints the level a seed makes and how the solver finished it
+pub fn run(args: &[String]) -> Result<()> {
+    let seed = match args.first() {
+        Some(arg) => arg
+            .parse()
+            .with_context(|| format!("Invalid seed {}", arg))?,
+        None => 0,
+    };
+    let generated = generate(seed)?;
+    println!("{}", generated.code);
+    println!(
+        "{}",
+        ron::ser::to_string_pretty(&generated.file, ron::ser::PrettyConfig::new())?
+    );
+    println!(
+        "Solved in {:.2}s, {} states searched",
+        generated.solution.time, generated.solution.states
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serialized(file: &LevelFile) -> String {
+        ron::ser::to_string(file).unwrap()
+    }
+
+    #[test]
+    fn rng_repeats_for_a_seed() {
+        let numbers = |seed| {
+            let mut rng = Rng(seed);
+            (0..16).map(|_| rng.next()).collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(42), numbers(42));
+        assert_ne!(numbers(42), numbers(43));
+    }
+
+    #[test]
+    fn rng_range_stays_in_bounds() {
+        let mut rng = Rng(7);
+        for _ in 0..1000 {
+            assert!((3..5).contains(&rng.range(3..5)));
+        }
+    }
+
+    #[test]
+    fn layout_repeats_for_a_seed() {
+        for seed in 0..8 {
+            let (file, code) = layout(&mut Rng(seed), seed);
+            let (again, again_code) = layout(&mut Rng(seed), seed);
+            assert_eq!(code, again_code);
+            assert_eq!(serialized(&file), serialized(&again));
+            file.validate().unwrap();
+        }
+    }
+}
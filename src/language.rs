@@ -1,139 +1,790 @@
 use crate::assets::*;
+use crate::console::Console;
 use lang_c::ast::*;
+use lang_c::span::{Node, Span};
+use std::collections::BTreeMap;
 
-pub fn compile(code: &str) -> CompilationStatus {
-    let code = code
-        .split('\n')
-        .filter(|line| !line.starts_with('#'))
-        .filter(|line| !line.trim().starts_with("//"))
+/// Strips preprocessor directives, comment lines and `$` slots from the level code.
+/// Everything is replaced with whitespace, so spans in the AST match offsets in the original code.
+pub fn preprocess(code: &str) -> String {
+    code.split('\n')
+        .map(|line| {
+            if line.starts_with('#') || line.trim().starts_with("//") {
+                " ".repeat(line.len())
+            } else {
+                line.replace('$', " ")
+            }
+        })
         .collect::<Vec<_>>()
         .join("\n")
-        .replace('$', "");
-    match lang_c::driver::parse_preprocessed(&lang_c::driver::Config::default(), code) {
+}
+
+pub fn parse(code: &str) -> Result<lang_c::driver::Parse, lang_c::driver::SyntaxError> {
+    lang_c::driver::parse_preprocessed(&lang_c::driver::Config::default(), preprocess(code))
+}
+
+pub fn compile(code: &str) -> CompilationStatus {
+    match parse(code) {
         Result::Ok(program) => {
-            let mut loops = Vec::new();
             for object in program.unit.0 {
                 if let ExternalDeclaration::FunctionDefinition(node) = object.node {
                     if let DeclaratorKind::Identifier(id) = node.node.declarator.node.kind.node {
                         if id.node.name == "main" {
                             // Process main function code
-                            if let Statement::Compound(nodes) = node.node.statement.node {
-                                for node in nodes {
-                                    if let BlockItem::Statement(statement) = node.node {
-                                        if let Statement::While(statement) = statement.node {
-                                            if let Statement::Compound(statements) =
-                                                statement.node.statement.node
-                                            {
-                                                loops.push(Loop::new(
-                                                    statements
-                                                        .into_iter()
-                                                        .filter_map(|statement| {
-                                                            if let BlockItem::Statement(statement) =
-                                                                statement.node
-                                                            {
-                                                                Some(statement.node)
-                                                            } else {
-                                                                None
-                                                            }
-                                                        })
-                                                        .collect(),
-                                                ));
-                                            }
-                                        }
-                                    }
-                                }
+                            if let Statement::Compound(items) = node.node.statement.node {
+                                return CompilationStatus::Success(Program::new(items));
                             }
                         }
                     }
                 }
             }
-            CompilationStatus::Success(loops)
+            CompilationStatus::Success(Program::new(Vec::new()))
+        }
+        Err(error) => {
+            let mut expected = error.expected.into_iter().collect::<Vec<_>>();
+            expected.sort_unstable();
+            CompilationStatus::Failure(CompileError {
+                offset: error.offset,
+                message: if expected.is_empty() {
+                    "syntax error".to_owned()
+                } else {
+                    format!("expected {}", expected.join(" or "))
+                },
+            })
         }
-        Err(_) => CompilationStatus::Failure,
     }
 }
 
 #[derive(Clone, Debug)]
 pub enum CompilationStatus {
     None,
-    Success(Vec<Loop>),
-    Failure,
+    Success(Program),
+    Failure(CompileError),
+}
+
+#[derive(Clone, Debug)]
+pub struct CompileError {
+    pub offset: usize,
+    pub message: String,
+}
+
+// * -------------------------------------------------------------------------------- Globals -------------------------------------------------------------------------------- * //
+#[derive(Clone, Debug)]
+pub struct Global {
+    pub value: i64,
+    /// Added to the value every frame
+    pub per_frame: i64,
+}
+
+/// Integer variables shared between the level code and the game
+#[derive(Clone, Debug, Default)]
+pub struct Globals(BTreeMap<String, Global>);
+
+impl Globals {
+    pub fn declare(&mut self, name: impl Into<String>, value: i64, per_frame: i64) {
+        self.0.insert(name.into(), Global { value, per_frame });
+    }
+
+    pub fn get(&self, name: &str) -> Option<i64> {
+        self.0.get(name).map(|global| global.value)
+    }
+
+    /// Returns false if there is no such global
+    pub fn set(&mut self, name: &str, value: i64) -> bool {
+        if let Some(global) = self.0.get_mut(name) {
+            global.value = value;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn add(&mut self, name: &str, amount: i64) -> bool {
+        match self.get(name) {
+            Some(value) => self.set(name, value.saturating_add(amount)),
+            None => false,
+        }
+    }
+
+    pub fn update(&mut self) {
+        for global in self.0.values_mut() {
+            global.value = global.value.saturating_add(global.per_frame);
+        }
+    }
+}
+
+// * -------------------------------------------------------------------------------- Program -------------------------------------------------------------------------------- * //
+/// Body of `main`. Plain statements run instantly, top-level loops run one iteration per frame
+#[derive(Clone, Debug)]
+pub struct Program {
+    items: Vec<Item>,
+    current: usize,
+    /// Variables declared directly in `main`, they live until the program is recompiled
+    locals: BTreeMap<String, i64>,
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Declaration(Node<Declaration>),
+    Statement(Node<Statement>),
+    Loop(Loop),
+}
+
+impl Program {
+    pub fn new(items: Vec<Node<BlockItem>>) -> Self {
+        Self {
+            items: items
+                .into_iter()
+                .filter_map(|item| match item.node {
+                    BlockItem::Declaration(declaration) => Some(Item::Declaration(declaration)),
+                    BlockItem::Statement(statement) => Some(
+                        Loop::new(&statement)
+                            .map(Item::Loop)
+                            .unwrap_or(Item::Statement(statement)),
+                    ),
+                    BlockItem::StaticAssert(_) => None,
+                })
+                .collect(),
+            current: 0,
+            locals: BTreeMap::new(),
+        }
+    }
+
+    /// Runs the program until the next loop iteration. Returns true once `main` is finished
+    pub fn frame<F: FnMut(&str, &mut Globals) -> Option<bool>>(
+        &mut self,
+        api_layer: F,
+        console: &mut Console,
+        globals: &mut Globals,
+    ) -> bool {
+        let mut runtime = Runtime {
+            api_layer,
+            console,
+            globals,
+            scopes: vec![std::mem::take(&mut self.locals)],
+        };
+        let finished = self.run(&mut runtime);
+        self.locals = runtime.scopes.swap_remove(0);
+        finished
+    }
+
+    fn run<F: FnMut(&str, &mut Globals) -> Option<bool>>(
+        &mut self,
+        runtime: &mut Runtime<F>,
+    ) -> bool {
+        while let Some(item) = self.items.get_mut(self.current) {
+            let flow = match item {
+                Item::Declaration(declaration) => {
+                    runtime.declare(declaration);
+                    Flow::Normal
+                }
+                Item::Statement(statement) => runtime.execute(statement),
+                Item::Loop(item) => match item.iteration(runtime) {
+                    Flow::Normal => return false,
+                    flow => flow,
+                },
+            };
+            if let Flow::Return = flow {
+                self.current = self.items.len();
+                return true;
+            }
+            self.current += 1;
+            if let Some(Item::Loop(_)) = self.items.get(self.current - 1) {
+                // The rest of `main` runs on the next frame
+                return self.current >= self.items.len();
+            }
+        }
+        true
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Loop {
-    statements: Vec<Statement>,
+    initializer: Option<ForInitializer>,
+    condition: Option<Node<Expression>>,
+    step: Option<Node<Expression>>,
+    body: Node<Statement>,
+    /// `do {} while ();` checks its condition after the body
+    check_after: bool,
+    started: bool,
 }
 
 impl Loop {
-    pub fn new(statements: Vec<Statement>) -> Self {
-        Self { statements }
+    pub fn new(statement: &Node<Statement>) -> Option<Self> {
+        let (initializer, condition, step, body, check_after) = match &statement.node {
+            Statement::While(node) => (
+                None,
+                Some(&node.node.expression),
+                None,
+                &node.node.statement,
+                false,
+            ),
+            Statement::DoWhile(node) => (
+                None,
+                Some(&node.node.expression),
+                None,
+                &node.node.statement,
+                true,
+            ),
+            Statement::For(node) => (
+                Some(node.node.initializer.node.clone()),
+                node.node.condition.as_ref(),
+                node.node.step.as_ref(),
+                &node.node.statement,
+                false,
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            initializer,
+            condition: condition.map(|condition| (**condition).clone()),
+            step: step.map(|step| (**step).clone()),
+            body: (**body).clone(),
+            check_after,
+            started: false,
+        })
     }
 
-    #[allow(clippy::collapsible_match)]
-    pub fn iteration<F: FnMut(&str) -> bool>(&self, mut api_layer: F) -> u32 {
-        for statement in &self.statements {
-            let breaks = execute(&mut api_layer, statement);
-            if breaks > 0 {
-                return breaks;
+    fn iteration<F: FnMut(&str, &mut Globals) -> Option<bool>>(
+        &mut self,
+        runtime: &mut Runtime<F>,
+    ) -> Flow {
+        if self.started {
+            if let Some(step) = &self.step {
+                runtime.evaluate(step);
+            }
+        } else {
+            self.started = true;
+            match &self.initializer {
+                Some(ForInitializer::Expression(expression)) => {
+                    runtime.evaluate(expression);
+                }
+                Some(ForInitializer::Declaration(declaration)) => runtime.declare(declaration),
+                _ => (),
+            }
+        }
+
+        if !self.check_after && !self.condition_holds(runtime) {
+            return Flow::Break;
+        }
+        match runtime.execute(&self.body) {
+            Flow::Break => Flow::Break,
+            Flow::Return => Flow::Return,
+            Flow::Normal | Flow::Continue if self.check_after && !self.condition_holds(runtime) => {
+                Flow::Break
             }
+            Flow::Normal | Flow::Continue => Flow::Normal,
         }
-        0
+    }
+
+    fn condition_holds<F: FnMut(&str, &mut Globals) -> Option<bool>>(
+        &self,
+        runtime: &mut Runtime<F>,
+    ) -> bool {
+        self.condition
+            .as_ref()
+            .map_or(true, |condition| runtime.evaluate(condition).truthy())
     }
 }
 
-fn evaluate<F: FnMut(&str) -> bool>(api_layer: &mut F, expression: &Expression) -> bool {
-    match expression {
-        Expression::Call(node) => {
-            if let Expression::Identifier(name) = &node.node.callee.node {
-                api_layer(&name.node.name)
-            } else {
-                println!(
-                    "Warning: unimplemented function expression: {:#?}",
-                    node.node.callee.node
-                );
-                false
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+}
+
+impl Value {
+    pub fn truthy(&self) -> bool {
+        match self {
+            Self::Int(value) => *value != 0,
+            Self::Str(_) => true,
+        }
+    }
+
+    fn int(&self) -> i64 {
+        match self {
+            Self::Int(value) => *value,
+            Self::Str(_) => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{}", value),
+            Self::Str(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// * -------------------------------------------------------------------------------- Runtime -------------------------------------------------------------------------------- * //
+struct Runtime<'a, F: FnMut(&str, &mut Globals) -> Option<bool>> {
+    /// Returns `None` for functions the game doesn't implement
+    api_layer: F,
+    console: &'a mut Console,
+    globals: &'a mut Globals,
+    /// Variables declared in the code, innermost block last. They shadow globals
+    scopes: Vec<BTreeMap<String, i64>>,
+}
+
+impl<'a, F: FnMut(&str, &mut Globals) -> Option<bool>> Runtime<'a, F> {
+    /// Nested loops have to finish within a frame, this stops them from freezing the game
+    const MAX_NESTED_ITERATIONS: u32 = 10000;
+
+    fn warn(&mut self, message: impl Into<String>, span: Span) {
+        self.console.diagnostics.warn(message, Some(span));
+    }
+
+    fn declare(&mut self, declaration: &Node<Declaration>) {
+        for declarator in &declaration.node.declarators {
+            if let DeclaratorKind::Identifier(name) = &declarator.node.declarator.node.kind.node {
+                let value = match &declarator.node.initializer {
+                    Some(initializer) => match &initializer.node {
+                        Initializer::Expression(expression) => self.evaluate(expression).int(),
+                        Initializer::List(_) => {
+                            self.warn("initializer lists are not supported", initializer.span);
+                            0
+                        }
+                    },
+                    None => 0,
+                };
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(name.node.name.clone(), value);
+                }
             }
         }
-        Expression::BinaryOperator(node) => {
-            if node.node.operator.node == BinaryOperator::LogicalAnd {
-                evaluate(api_layer, &node.node.lhs.node) && evaluate(api_layer, &node.node.rhs.node)
-            } else {
-                println!(
-                    "Warning: unimplemented binary operator: {:#?}",
-                    node.node.operator.node
-                );
-                false
+    }
+
+    fn variable(&self, name: &str) -> Option<i64> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+            .or_else(|| self.globals.get(name))
+    }
+
+    /// Returns false if there is no such local or global
+    fn set_variable(&mut self, name: &str, value: i64) -> bool {
+        match self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            Some(local) => {
+                *local = value;
+                true
             }
+            None => self.globals.set(name, value),
         }
-        _ => {
-            println!("Warning: unimplemented expression: {:#?}", expression);
-            false
+    }
+
+    fn assign(&mut self, target: &Node<Expression>, value: i64) -> Value {
+        if let Expression::Identifier(name) = &target.node {
+            if !self.set_variable(&name.node.name, value) {
+                self.warn(
+                    format!("undeclared identifier {}", name.node.name),
+                    target.span,
+                );
+            }
+        } else {
+            self.warn("can only assign to variables", target.span);
         }
+        Value::Int(value)
     }
-}
 
-fn execute<F: FnMut(&str) -> bool>(api_layer: &mut F, statement: &Statement) -> u32 {
-    match statement {
-        Statement::Expression(expr) => {
-            if let Some(expr) = expr {
-                if let Expression::Call(node) = &expr.node {
-                    if let Expression::Identifier(name) = &node.node.callee.node {
-                        api_layer(&name.node.name);
+    fn evaluate(&mut self, expression: &Node<Expression>) -> Value {
+        match &expression.node {
+            Expression::Identifier(name) => match self.variable(&name.node.name) {
+                Some(value) => Value::Int(value),
+                None => {
+                    self.warn(
+                        format!("undeclared identifier {}", name.node.name),
+                        expression.span,
+                    );
+                    Value::Int(0)
+                }
+            },
+            Expression::Call(node) => {
+                if let Expression::Identifier(name) = &node.node.callee.node {
+                    let arguments = node
+                        .node
+                        .arguments
+                        .iter()
+                        .map(|argument| self.evaluate(argument))
+                        .collect::<Vec<_>>();
+                    match name.node.name.as_str() {
+                        "printf" => return printf(self.console, &arguments, expression.span),
+                        "puts" => {
+                            if let Some(text) = arguments.first() {
+                                self.console.print(&format!("{}\n", text));
+                            }
+                            return Value::Int(0);
+                        }
+                        _ => (),
                     }
+                    // Globals can be read like functions, e.g. `textures_left()`
+                    if let Some(value) = self.globals.get(&name.node.name) {
+                        return Value::Int(value);
+                    }
+                    match (self.api_layer)(&name.node.name, &mut *self.globals) {
+                        Some(value) => Value::Int(value as i64),
+                        None => {
+                            self.warn(
+                                format!("unimplemented function {}", name.node.name),
+                                expression.span,
+                            );
+                            Value::Int(0)
+                        }
+                    }
+                } else {
+                    self.warn("unimplemented function expression", node.node.callee.span);
+                    Value::Int(0)
                 }
             }
-            0
+            Expression::Constant(constant) => match &constant.node {
+                Constant::Integer(integer) => {
+                    let radix = match integer.base {
+                        IntegerBase::Decimal => 10,
+                        IntegerBase::Octal => 8,
+                        IntegerBase::Hexadecimal => 16,
+                        IntegerBase::Binary => 2,
+                    };
+                    Value::Int(
+                        i64::from_str_radix(&integer.number, radix).unwrap_or_else(|_| {
+                            self.warn("integer constant is too large", expression.span);
+                            0
+                        }),
+                    )
+                }
+                Constant::Character(character) => {
+                    let character = unescape(unquote(character, '\''));
+                    Value::Int(character.chars().next().map_or(0, |char| char as i64))
+                }
+                Constant::Float(_) => {
+                    self.warn("floating point is not supported", expression.span);
+                    Value::Int(0)
+                }
+            },
+            Expression::StringLiteral(literal) => Value::Str(
+                literal
+                    .node
+                    .iter()
+                    .map(|part| unescape(unquote(part, '"')))
+                    .collect(),
+            ),
+            Expression::UnaryOperator(node) => {
+                let operand = self.evaluate(&node.node.operand);
+                match node.node.operator.node {
+                    UnaryOperator::Negate => Value::Int(!operand.truthy() as i64),
+                    UnaryOperator::Minus => Value::Int(operand.int().wrapping_neg()),
+                    UnaryOperator::Plus => operand,
+                    UnaryOperator::PreIncrement => {
+                        self.assign(&node.node.operand, operand.int().wrapping_add(1))
+                    }
+                    UnaryOperator::PreDecrement => {
+                        self.assign(&node.node.operand, operand.int().wrapping_sub(1))
+                    }
+                    UnaryOperator::PostIncrement => {
+                        self.assign(&node.node.operand, operand.int().wrapping_add(1));
+                        operand
+                    }
+                    UnaryOperator::PostDecrement => {
+                        self.assign(&node.node.operand, operand.int().wrapping_sub(1));
+                        operand
+                    }
+                    ref operator => {
+                        self.warn(
+                            format!("unimplemented unary operator {:?}", operator),
+                            node.node.operator.span,
+                        );
+                        Value::Int(0)
+                    }
+                }
+            }
+            Expression::BinaryOperator(node) => {
+                if node.node.operator.node == BinaryOperator::Assign {
+                    let value = self.evaluate(&node.node.rhs).int();
+                    return self.assign(&node.node.lhs, value);
+                }
+
+                let lhs = self.evaluate(&node.node.lhs);
+                // Logical operators short-circuit
+                match node.node.operator.node {
+                    BinaryOperator::LogicalAnd if !lhs.truthy() => return Value::Int(0),
+                    BinaryOperator::LogicalOr if lhs.truthy() => return Value::Int(1),
+                    _ => (),
+                }
+                let rhs = self.evaluate(&node.node.rhs);
+                let (a, b) = (lhs.int(), rhs.int());
+                let value = match node.node.operator.node {
+                    BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => rhs.truthy() as i64,
+                    BinaryOperator::Plus | BinaryOperator::AssignPlus => a.wrapping_add(b),
+                    BinaryOperator::Minus | BinaryOperator::AssignMinus => a.wrapping_sub(b),
+                    BinaryOperator::Multiply | BinaryOperator::AssignMultiply => a.wrapping_mul(b),
+                    BinaryOperator::Divide
+                    | BinaryOperator::AssignDivide
+                    | BinaryOperator::Modulo
+                    | BinaryOperator::AssignModulo
+                        if b == 0 =>
+                    {
+                        self.warn("division by zero", expression.span);
+                        0
+                    }
+                    BinaryOperator::Divide | BinaryOperator::AssignDivide => a.wrapping_div(b),
+                    BinaryOperator::Modulo | BinaryOperator::AssignModulo => a.wrapping_rem(b),
+                    BinaryOperator::Less => (a < b) as i64,
+                    BinaryOperator::Greater => (a > b) as i64,
+                    BinaryOperator::LessOrEqual => (a <= b) as i64,
+                    BinaryOperator::GreaterOrEqual => (a >= b) as i64,
+                    BinaryOperator::Equals => (lhs == rhs) as i64,
+                    BinaryOperator::NotEquals => (lhs != rhs) as i64,
+                    ref operator => {
+                        self.warn(
+                            format!("unimplemented binary operator {:?}", operator),
+                            node.node.operator.span,
+                        );
+                        0
+                    }
+                };
+                match node.node.operator.node {
+                    BinaryOperator::AssignPlus
+                    | BinaryOperator::AssignMinus
+                    | BinaryOperator::AssignMultiply
+                    | BinaryOperator::AssignDivide
+                    | BinaryOperator::AssignModulo => self.assign(&node.node.lhs, value),
+                    _ => Value::Int(value),
+                }
+            }
+            _ => {
+                self.warn("unimplemented expression", expression.span);
+                Value::Int(0)
+            }
         }
-        Statement::Break => 1,
-        Statement::Return(_) => u32::MAX,
-        Statement::If(statement) => {
-            if evaluate(api_layer, &statement.node.condition.node) {
-                return execute(api_layer, &statement.node.then_statement.node);
+    }
+
+    fn execute(&mut self, statement: &Node<Statement>) -> Flow {
+        match &statement.node {
+            Statement::Expression(expr) => {
+                if let Some(expr) = expr {
+                    self.evaluate(expr);
+                }
+                Flow::Normal
+            }
+            Statement::Compound(items) => {
+                self.scopes.push(BTreeMap::new());
+                let flow = self.block(items);
+                self.scopes.pop();
+                flow
+            }
+            Statement::Break => Flow::Break,
+            Statement::Continue => Flow::Continue,
+            Statement::Return(_) => Flow::Return,
+            Statement::If(node) => {
+                if self.evaluate(&node.node.condition).truthy() {
+                    self.execute(&node.node.then_statement)
+                } else if let Some(else_statement) = &node.node.else_statement {
+                    self.execute(else_statement)
+                } else {
+                    Flow::Normal
+                }
             }
-            0
+            Statement::While(_) | Statement::DoWhile(_) | Statement::For(_) => {
+                // Variables declared in a `for` initializer end with the loop
+                self.scopes.push(BTreeMap::new());
+                let flow = self.nested_loop(statement);
+                self.scopes.pop();
+                flow
+            }
+            Statement::Goto(_) => {
+                self.warn("goto is not supported", statement.span);
+                Flow::Normal
+            }
+            _ => Flow::Normal,
         }
-        Statement::Goto(_) => todo!(),
-        _ => 0,
+    }
+
+    fn block(&mut self, items: &[Node<BlockItem>]) -> Flow {
+        for item in items {
+            let flow = match &item.node {
+                BlockItem::Declaration(declaration) => {
+                    self.declare(declaration);
+                    Flow::Normal
+                }
+                BlockItem::Statement(statement) => self.execute(statement),
+                BlockItem::StaticAssert(_) => Flow::Normal,
+            };
+            if flow != Flow::Normal {
+                return flow;
+            }
+        }
+        Flow::Normal
+    }
+
+    fn nested_loop(&mut self, statement: &Node<Statement>) -> Flow {
+        let mut nested = Loop::new(statement).expect("statement is a loop");
+        for _ in 0..Self::MAX_NESTED_ITERATIONS {
+            match nested.iteration(self) {
+                Flow::Normal | Flow::Continue => (),
+                Flow::Break => return Flow::Normal,
+                Flow::Return => return Flow::Return,
+            }
+        }
+        self.warn("nested loop takes too long, skipping it", statement.span);
+        Flow::Normal
+    }
+}
+
+/// Supports `%d`, `%i`, `%s`, `%c` and `%%`
+fn printf(console: &mut Console, arguments: &[Value], span: Span) -> Value {
+    let (format, arguments) = match arguments.split_first() {
+        Some((Value::Str(format), arguments)) => (format, arguments),
+        _ => {
+            console
+                .diagnostics
+                .warn("printf expects a format string", Some(span));
+            return Value::Int(-1);
+        }
+    };
+
+    let mut arguments = arguments.iter();
+    let mut text = String::new();
+    let mut chars = format.chars();
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            text.push(char);
+            continue;
+        }
+        let specifier = chars.next();
+        if specifier == Some('%') {
+            text.push('%');
+            continue;
+        }
+        let argument = match arguments.next() {
+            Some(argument) => argument,
+            None => {
+                console
+                    .diagnostics
+                    .warn("too few arguments for format", Some(span));
+                break;
+            }
+        };
+        match (specifier, argument) {
+            (Some('d' | 'i'), Value::Int(value)) => text += &value.to_string(),
+            (Some('s'), Value::Str(value)) => text += value,
+            (Some('c'), Value::Int(value)) => {
+                text.extend(char::from_u32(*value as u32));
+            }
+            (specifier, argument) => {
+                console.diagnostics.warn(
+                    format!(
+                        "format %{} doesn't match argument {}",
+                        specifier.unwrap_or(' '),
+                        argument
+                    ),
+                    Some(span),
+                );
+                text += &argument.to_string();
+            }
+        }
+    }
+    console.print(&text);
+    Value::Int(text.len() as i64)
+}
+
+fn unquote(text: &str, quote: char) -> &str {
+    text.strip_prefix(quote)
+        .and_then(|text| text.strip_suffix(quote))
+        .unwrap_or(text)
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('0') => break,
+            Some(char) => unescaped.push(char),
+            None => (),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printed(arguments: &[Value]) -> Console {
+        let mut console = Console::default();
+        printf(&mut console, arguments, Span { start: 0, end: 0 });
+        console
+    }
+
+    fn warnings(console: &Console) -> Vec<&str> {
+        console
+            .diagnostics
+            .entries()
+            .iter()
+            .map(|entry| entry.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn printf_fills_in_arguments() {
+        let console = printed(&[
+            Value::Str("%d %s, %c%%\n".to_owned()),
+            Value::Int(3),
+            Value::Str("apples".to_owned()),
+            Value::Int('A' as i64),
+        ]);
+        assert_eq!(console.stdout(), ["3 apples, A%", ""]);
+        assert!(warnings(&console).is_empty());
+    }
+
+    #[test]
+    fn printf_continues_the_last_line() {
+        let mut console = Console::default();
+        let span = Span { start: 0, end: 0 };
+        printf(&mut console, &[Value::Str("a".to_owned())], span);
+        printf(&mut console, &[Value::Str("b\nc".to_owned())], span);
+        assert_eq!(console.stdout(), ["ab", "c"]);
+    }
+
+    #[test]
+    fn printf_warns_about_bad_arguments() {
+        let console = printed(&[Value::Str("%s".to_owned()), Value::Int(3)]);
+        assert_eq!(console.stdout(), ["3"]);
+        assert_eq!(warnings(&console), ["format %s doesn't match argument 3"]);
+
+        let console = printed(&[Value::Str("%d".to_owned())]);
+        assert_eq!(warnings(&console), ["too few arguments for format"]);
+
+        let console = printed(&[Value::Int(1)]);
+        assert_eq!(warnings(&console), ["printf expects a format string"]);
+        assert!(console.stdout().is_empty());
+    }
+
+    #[test]
+    fn values_format_like_printf_arguments() {
+        assert_eq!(Value::Int(-4).to_string(), "-4");
+        assert_eq!(Value::Str("hi".to_owned()).to_string(), "hi");
+        assert!(Value::Str(String::new()).truthy());
+        assert!(!Value::Int(0).truthy());
     }
 }
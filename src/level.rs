@@ -1,268 +1,323 @@
+use crate::analysis::*;
 use crate::assets::*;
+use crate::camera::*;
+use crate::console::Console;
 use crate::language::*;
+use crate::level_file::*;
+use crate::platform::*;
 use crate::player::Player;
+use crate::tilemap::TileMap;
+use lang_c::span::Span;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A token that was put into the code, `span` covers the text it was expanded to
+#[derive(Clone, Debug)]
+pub struct PlacedToken {
+    pub token: Token,
+    pub span: Span,
+    /// Declared kind of the slot the token went into, restored when it's ejected
+    pub slot_kind: Option<SlotKind>,
+}
+
+/// State right after a token was placed or at the spawn, restarting and dying go back here
+#[derive(Clone)]
+pub struct Checkpoint {
+    level: Box<Level>,
+    player: Player,
+}
 
 #[derive(Clone)]
 pub struct Level {
     index: usize,
     code: String,
     status: CompilationStatus,
-    platforms: Vec<Rectangle>,
+    platforms: Vec<Platform>,
     pub tokens: Vec<Token>,
+    /// Tokens the player picked up and hasn't put into the code yet
+    inventory: Vec<Token>,
+    selected_item: usize,
+    placed: Vec<PlacedToken>,
     enemies: Vec<Enemy>,
+    loop_reports: Vec<LoopReport>,
+    console: Console,
+    pub globals: Globals,
 
     cursor_blink_timer: f32,
-    pub cursor_target_position: Option<Vector2>,
+    /// Slot collected tokens go into, counting `$` from the top of the code
+    selected_slot: usize,
+    /// Kinds the level file declares for each slot, the rest are inferred from the code
+    declared_slots: Vec<Option<SlotKind>>,
+    pub show_hints: bool,
+    files: Vec<PathBuf>,
+    /// Sounds to play this frame
+    pub sounds: Vec<SoundEffect>,
+    /// Set by `lagB()`, the game runs at 10 FPS until the next token is placed
+    pub lagging: bool,
+    checkpoint: Option<Checkpoint>,
+    /// Touching enemies doesn't kill the player
+    friendly_enemies: bool,
+    world_size: Vector2,
+    camera: FollowCamera,
+    /// Ground drawn as a grid, its collision rectangles are among the platforms
+    tiles: Option<TileMap>,
 }
 
 impl Level {
     pub fn new(
         code: String,
-        platforms: Vec<Rectangle>,
+        platforms: Vec<Platform>,
         tokens: Vec<Token>,
         enemies: Vec<Enemy>,
     ) -> Self {
+        let declared_slots = vec![None; code.matches('$').count()];
         Self {
             index: 0,
             code,
             status: CompilationStatus::None,
             platforms,
             tokens,
+            inventory: Vec::new(),
+            selected_item: 0,
+            placed: Vec::new(),
             enemies,
+            loop_reports: Vec::new(),
+            console: Console::default(),
+            globals: Globals::default(),
             cursor_blink_timer: 0.0,
-            cursor_target_position: None,
-        }
-    }
-
-    pub fn load(index: usize) -> Option<(Self, Player)> {
-        let player_size = rvec2(60, 80);
-        let levels = [
-            (
-                Self::new(
-                    include_str!("levels/code1.c").to_owned(),
-                    vec![rrect(600, 670, 200, 70)],
-                    vec![
-                        Token::new("shrink_player();", rvec2(700, 670)),
-                        Token::new("break;", rvec2(740, 800)),
-                    ],
-                    vec![],
-                ),
-                Player::new(rvec2(10, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code2.c").to_owned(),
-                    vec![rrect(400, 660, 200, 80), rrect(600, 660, 200, 50)],
-                    vec![
-                        Token::new("break;", rvec2(600, 660)),
-                        Token::new("break;", rvec2(700, 800)),
-                    ],
-                    vec![],
-                ),
-                Player::new(rvec2(10, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code3.c").to_owned(),
-                    vec![
-                        rrect(600, 640, 200, 80),
-                        rrect(300, 640, 200, 80),
-                        rrect(300, 480, 100, 80),
-                        rrect(300, 330, 100, 80),
-                        rrect(600, 290, 200, 80),
-                    ],
-                    vec![
-                        Token::new("shrink_player();", rvec2(400, 630)),
-                        Token::new("increase_jumps();", rvec2(700, 630)),
-                        Token::new("break;", rvec2(700, 290)),
-                    ],
-                    vec![],
-                ),
-                Player::new(rvec2(10, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code4.c").to_owned(),
-                    vec![
-                        rrect(300, 500, 240, 80),
-                        rrect(632, 290, 168, 80),
-                        rrect(680, 640, 75, 110),
-                        rrect(680, 750, 20, 50),
-                    ],
-                    vec![
-                        Token::new("increase_jumps();", rvec2(420, 500)),
-                        Token::new("shrink_player();", rvec2(710, 290)),
-                        Token::new("break;", rvec2(750, 800)),
-                    ],
-                    vec![],
-                ),
-                Player::new(rvec2(10, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code5.c").to_owned(),
-                    vec![],
-                    vec![
-                        Token::new("shrink_player();", rvec2(600, 800)),
-                        Token::new("break;", rvec2(750, 800)),
-                    ],
-                    vec![Enemy::new(rvec2(250, 800))],
-                ),
-                Player::new(rvec2(10, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code6.c").to_owned(),
-                    vec![rrect(390, 640, 20, 160), rrect(190, 640, 200, 70)],
-                    vec![
-                        Token::new("player_is_jumping()", rvec2(300, 630)),
-                        Token::new("break;", rvec2(750, 800)),
-                    ],
-                    vec![Enemy::new(rvec2(410, 800))],
-                ),
-                Player::new(rvec2(10, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code7.c").to_owned(),
-                    vec![rrect(300, 640, 10, 160)],
-                    vec![
-                        Token::new("shrink_player();", rvec2(710, 800)),
-                        Token::new("break;", rvec2(350, 800)),
-                    ],
-                    vec![Enemy::new(rvec2(390, 800))],
-                ),
-                Player::new(rvec2(10, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code8.c").to_owned(),
-                    vec![rrect(390, 600, 20, 200)],
-                    vec![
-                        Token::new("shrink_player();", rvec2(500, 800)),
-                        Token::new("break;", rvec2(350, 800)),
-                    ],
-                    vec![Enemy::new(rvec2(590, 800))],
-                ),
-                Player::new(rvec2(180, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code9.c").to_owned(),
-                    vec![
-                        rrect(390, 400, 20, 400),
-                        rrect(0, 640, 100, 70),
-                        rrect(410, 640, 100, 70),
-                        rrect(340, 560, 50, 80),
-                        rrect(750, 560, 50, 80),
-                    ],
-                    vec![
-                        Token::new("lagB();", rvec2(250, 600)),
-                        Token::new("break;", rvec2(665, 575)),
-                    ],
-                    vec![Enemy::new(rvec2(575, 800))],
-                ),
-                Player::new(rvec2(165, 800.0 - player_size.y - 1.0), player_size),
-            ),
-            (
-                Self::new(
-                    include_str!("levels/code10.c").to_owned(),
-                    vec![
-                        rrect(390, 0, 20, 800),
-                        rrect(410, 390, 390, 20),
-                        rrect(0, 640, 95, 70),
-                        rrect(410, 640, 105, 70),
-                        rrect(410, 220, 95, 80),
-                        // rrect(410, 630, 100, 80),
-                        // rrect(340, 560, 50, 80),
-                        // rrect(750, 560, 50, 80),
-                    ],
-                    vec![
-                        Token::new("lagB();", rvec2(715, 390)),
-                        Token::new("lagB();", rvec2(305, 800)),
-                        Token::new("break;", rvec2(710, 800)),
-                    ],
-                    vec![Enemy::new(rvec2(575, 800)), Enemy::new(rvec2(575, 390))],
-                ),
-                Player::new(rvec2(165, 800.0 - player_size.y - 1.0), player_size),
-            ),
-        ];
+            selected_slot: 0,
+            declared_slots,
+            show_hints: false,
+            files: Vec::new(),
+            sounds: Vec::new(),
+            lagging: false,
+            checkpoint: None,
+            friendly_enemies: false,
+            world_size: rvec2(SCREEN_SIZE.0, SCREEN_SIZE.1),
+            camera: FollowCamera::new(rrect(0, 0, 0, 0), rvec2(SCREEN_SIZE.0, SCREEN_SIZE.1)),
+            tiles: None,
+        }
+    }
+
+    pub fn with_global(mut self, name: &str, value: i64, per_frame: i64) -> Self {
+        self.globals.declare(name, value, per_frame);
+        self
+    }
+
+    pub fn with_world_size(mut self, size: Vector2) -> Self {
+        self.world_size = size;
+        self
+    }
+
+    /// Adds the tile map's merged rectangles to the platforms
+    pub fn with_tiles(mut self, tiles: TileMap) -> Self {
+        self.platforms.extend(tiles.platforms());
+        self.tiles = Some(tiles);
+        self
+    }
+
+    pub fn with_friendly_enemies(mut self) -> Self {
+        self.friendly_enemies = true;
+        self
+    }
+
+    pub fn with_slot_kind(mut self, slot: usize, kind: SlotKind) -> Self {
+        if let Some(declared) = self.declared_slots.get_mut(slot) {
+            *declared = Some(kind);
+        }
+        self
+    }
+
+    /// Loads level number `index` from the list [`scan_levels`] found, returns `None` when there are no more levels
+    pub fn load(levels: &[PathBuf], index: usize) -> Result<Option<(Self, Player)>> {
+        let path = match levels.get(index) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let file = LevelFile::load(path)?;
+        let (mut level, player) = Self::from_file(&file, file.load_code(path)?, index);
+        level.files = vec![path.clone(), file.code_path(path)];
+        Ok(Some((level, player)))
+    }
 
-        levels.get(index).cloned().map(|mut level| {
-            level.0.index = index;
-            level.0.recompile();
-            level
-        })
+    pub fn from_file(file: &LevelFile, code: String, index: usize) -> (Self, Player) {
+        let (mut level, player) = file.build(code);
+        level.index = index;
+        level.recompile();
+        level.camera = FollowCamera::new(player.rect(), level.world_size);
+        level.save_checkpoint(&player);
+        (level, player)
+    }
+
+    /// Reloads the level from disk in place, keeping the player where they are if they still fit
+    pub fn reload(&mut self, levels: &[PathBuf], player: &mut Player) -> Result<()> {
+        let (mut level, mut new_player) =
+            Self::load(levels, self.index)?.context("Level was removed from the list")?;
+        level.show_hints = self.show_hints;
+
+        let spawn = new_player.position;
+        new_player.position = player.position;
+        new_player.velocity = player.velocity;
+        if new_player.collides(&mut level) {
+            new_player.position = spawn;
+            new_player.velocity = Vector2::zero();
+        }
+        level.camera = FollowCamera::new(new_player.rect(), level.world_size);
+
+        *self = level;
+        *player = new_player;
+        Ok(())
     }
 
     // * --------------------------------------------------------------------------------- Update --------------------------------------------------------------------------------- * //
-    pub fn update(
-        &mut self,
-        rl: &mut RaylibHandle,
-        assets: &mut Assets,
-        player: &mut Player,
-    ) -> bool {
-        self.cursor_blink_timer = (self.cursor_blink_timer + rl.get_frame_time()) % 1.0;
+    pub fn update(&mut self, rl: &mut RaylibHandle, player: &mut Player) -> bool {
+        self.console.update(rl);
+        if rl.is_key_pressed(KeyboardKey::KEY_Q) {
+            self.select_slot(self.selected_slot.saturating_sub(1));
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_E) {
+            self.select_slot(self.selected_slot + 1);
+        }
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT) {
+            if let Some(slot) = self.slot_at(rl.get_mouse_position()) {
+                self.select_slot(slot);
+            }
+        }
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+            if let Some(index) = self.placed_at(rl.get_mouse_position()) {
+                self.eject(index);
+            }
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) && !self.placed.is_empty() {
+            self.eject(self.placed.len() - 1);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_TAB) && !self.inventory.is_empty() {
+            self.selected_item = (self.selected_item + 1) % self.inventory.len();
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_F) {
+            self.insert(self.selected_item);
+        }
+        let finished = self.step(rl.get_frame_time(), player);
+        rl.set_target_fps(if self.lagging { 10 } else { i32::MAX as u32 });
+        finished
+    }
+
+    /// Advances the level by one frame without touching the window, returns true when the program has finished
+    pub fn step(&mut self, frame_time: f32, player: &mut Player) -> bool {
+        self.cursor_blink_timer = (self.cursor_blink_timer + frame_time) % 1.0;
+        self.camera
+            .update(frame_time, player.rect(), self.world_size);
+        for platform in &mut self.platforms {
+            let carrying = platform.supports(&player.rect());
+            if carrying {
+                platform.step_on();
+            }
+            let moved = platform.update(frame_time);
+            if carrying {
+                player.position += moved;
+            }
+        }
         let mut enemies = self.enemies.drain(..).collect::<Vec<_>>();
         for enemy in &mut enemies {
-            enemy.update(rl, assets, player, self);
+            enemy.update(frame_time, player, self);
         }
         self.enemies = enemies;
+        if !player.dead() && self.deadly(player) {
+            player.die();
+            self.sounds.push(SoundEffect::Death);
+        }
         for i in (0..self.tokens.len()).rev() {
-            let token = &mut self.tokens[i];
-            token.update(rl);
-            if token.finished {
-                self.code = self.code.replacen('$', token.format(), 1);
-                self.recompile();
-                self.tokens.remove(i);
-                assets.audio.play_sound(&assets.token_placed_sound);
-                if player.size.x < 60.0 {
-                    player.position -= rvec2(60, 80) - player.size;
-                    player.size = rvec2(60, 80);
+            self.tokens[i].update(
+                frame_time,
+                &self.platforms,
+                self.world_size.y,
+                self.camera.view(),
+            );
+            if self.tokens[i].finished {
+                let token = self.tokens.remove(i);
+                if !self.place(&token) {
+                    let position = token.position;
+                    self.drop_token(token, position);
+                    continue;
+                }
+                self.sounds.push(SoundEffect::TokenPlaced);
+                if player.size.x < player.default_size().x {
+                    player.position -= player.default_size() - player.size;
+                    player.size = player.default_size();
+                }
+                self.lagging = false;
+                // Respawning into a death would loop forever
+                if !player.dead() && !self.deadly(player) {
+                    self.save_checkpoint(player);
                 }
-                rl.set_target_fps(i32::MAX as u32);
             }
         }
-        if let CompilationStatus::Success(loops) = &mut self.status {
-            let api_layer = |function: &_| {
+        for token in &mut self.inventory {
+            token.rejected = (token.rejected - frame_time).max(0.0);
+        }
+        self.globals.update();
+        let switches = self
+            .platforms
+            .iter()
+            .filter_map(|platform| Some(platform.switch()?.function().to_owned()))
+            .collect::<Vec<_>>();
+        let mut called = Vec::new();
+        let mut finished = false;
+        if let CompilationStatus::Success(program) = &mut self.status {
+            let lagging = &mut self.lagging;
+            let api_layer = |function: &str, globals: &mut Globals| {
                 match function {
                     "shrink_player" => {
-                        player.size = rvec2(45, 60);
+                        player.size = player.default_size() * 0.75;
                     }
                     "increase_jumps" => {
                         player.max_jumps = 4;
                     }
                     "lagB" => {
-                        rl.set_target_fps(10);
+                        *lagging = true;
+                    }
+                    "free_texture" => {
+                        globals.add("textures_left", -1);
                     }
-                    "player_is_jumping" => return player.jumping(),
-                    "update_game" | "free_texture" => (),
-                    _ => {
-                        println!("Warning: Unimplemented function {}!", function)
+                    "game_is_running" => return Some(true),
+                    "player_is_jumping" => return Some(player.jumping()),
+                    "start_game" | "update_game" | "load_next_asset" => (),
+                    _ if switches.iter().any(|switch| switch == function) => {
+                        called.push(function.to_owned());
                     }
+                    _ => return None,
                 }
-                false
+                Some(false)
             };
 
-            if loops.is_empty() {
-                return true;
-            }
-            let breaks = loops[0].iteration(api_layer);
-            for _ in 0..breaks {
-                loops.remove(0);
-                if loops.is_empty() {
-                    break;
-                }
-            }
+            finished = program.frame(api_layer, &mut self.console, &mut self.globals);
         }
-        false
+        for platform in &mut self.platforms {
+            platform.react(&called);
+        }
+        finished
+    }
+
+    /// Whether the player is touching an enemy that kills or spikes
+    fn deadly(&self, player: &Player) -> bool {
+        let rect = player.rect();
+        let hit_enemy = !self.friendly_enemies
+            && self
+                .enemies
+                .iter()
+                .any(|enemy| enemy.rect().check_collision_recs(&rect));
+        hit_enemy || self.platforms.iter().any(|platform| platform.hurts(&rect))
     }
 
     // * ---------------------------------------------------------------------------------- Draw ---------------------------------------------------------------------------------- * //
-    pub fn draw<D: RaylibDraw>(&mut self, d: &mut D) {
+    /// Draws tiles, platforms, enemies and tokens, in world coordinates under [`Level::camera`]
+    pub fn draw_world<D: RaylibDraw>(&self, d: &mut D, tileset: &Texture2D) {
         d.clear_background(Color::RAYWHITE);
+        if let Some(tiles) = &self.tiles {
+            tiles.draw(d, tileset);
+        }
         for platform in &self.platforms {
-            d.draw_rectangle_rec(platform, Color::new(200, 200, 200, 255));
+            platform.draw(d);
         }
         for enemy in &self.enemies {
             enemy.draw(d);
@@ -270,44 +325,104 @@ impl Level {
         for token in &self.tokens {
             token.draw(d);
         }
+    }
 
+    /// Draws the code, console and inventory over the world, in screen coordinates
+    pub fn draw_hud<D: RaylibDraw>(&mut self, d: &mut D) {
         let font_size = 20;
         let cursor_width = (font_size as f32 * 0.6) as i32;
 
+        let hint_slots = if self.show_hints {
+            self.loop_reports
+                .iter()
+                .filter(|report| !report.terminates)
+                .flat_map(|report| report.exit_slots.iter().copied())
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+
+        let slot_kinds = (0..self.slot_count())
+            .map(|slot| self.slot_kind(slot))
+            .collect::<Vec<_>>();
         let mut cursor = rvec2(12, 30);
-        let mut passed_cursor = false;
+        let mut slot = 0;
         for line in self.code.split('\n') {
-            if !passed_cursor {
-                if let Some((left, right)) = line.split_once('$') {
-                    let advance = measure_text(left, font_size);
-                    d.draw_text(left, cursor.x as _, cursor.y as _, font_size, Color::GRAY);
-                    self.cursor_target_position = Some(cursor + rvec2(advance, font_size / 2));
-                    if self.cursor_blink_timer < 0.5 {
-                        d.draw_rectangle_v(
-                            cursor + rvec2(advance, 0),
-                            rvec2(cursor_width, font_size),
-                            Color::GRAY,
-                        );
-                    }
-                    let advance = advance + cursor_width;
-                    d.draw_text(
-                        &right.replace('$', ""),
-                        cursor.x as i32 + advance,
-                        cursor.y as _,
-                        font_size,
-                        Color::GRAY,
+            let mut advance = 0;
+            let mut parts = line.split('$').peekable();
+            while let Some(part) = parts.next() {
+                d.draw_text(
+                    part,
+                    cursor.x as i32 + advance,
+                    cursor.y as _,
+                    font_size,
+                    Color::GRAY,
+                );
+                advance += measure_text(part, font_size);
+                if parts.peek().is_none() {
+                    break;
+                }
+
+                let slot_position = cursor + rvec2(advance, 0);
+                d.draw_rectangle_lines(
+                    slot_position.x as _,
+                    slot_position.y as _,
+                    cursor_width,
+                    font_size,
+                    Color::LIGHTGRAY,
+                );
+                let kind = match slot_kinds[slot] {
+                    SlotKind::Expression => "e",
+                    SlotKind::Statement => "s",
+                };
+                d.draw_text(
+                    kind,
+                    slot_position.x as i32 + (cursor_width - measure_text(kind, 10)) / 2,
+                    slot_position.y as i32 + 5,
+                    10,
+                    Color::LIGHTGRAY,
+                );
+                if hint_slots.contains(&slot) {
+                    d.draw_rectangle_v(
+                        slot_position,
+                        rvec2(cursor_width, font_size),
+                        Color::ORANGE.fade(0.5),
+                    );
+                }
+                if slot == self.selected_slot {
+                    d.draw_rectangle_v(
+                        slot_position,
+                        rvec2(cursor_width, font_size),
+                        Color::GRAY.fade(if self.cursor_blink_timer < 0.5 {
+                            1.0
+                        } else {
+                            0.3
+                        }),
                     );
-                    passed_cursor = true;
-                    cursor.y += font_size as f32;
-                    continue;
                 }
+                advance += cursor_width;
+                slot += 1;
+            }
+            cursor.y += font_size as f32;
+        }
+        for placed in &self.placed {
+            let start = self.code_position(placed.span.start);
+            let end = self.code_position(placed.span.end);
+            if start.y == end.y {
+                d.draw_line_v(
+                    start + rvec2(0, font_size / 2),
+                    end + rvec2(0, font_size / 2),
+                    Color::RED.fade(0.5),
+                );
             }
+        }
+        if self.show_hints && !hint_slots.is_empty() {
             d.draw_text(
-                &line.replace('$', ""),
+                "Hint: this loop can only end through the highlighted slots",
                 cursor.x as _,
                 cursor.y as _,
                 font_size,
-                Color::GRAY,
+                Color::ORANGE,
             );
             cursor.y += font_size as f32;
         }
@@ -319,7 +434,7 @@ impl Level {
                 30,
                 Color::GREEN,
             ),
-            CompilationStatus::Failure => d.draw_text(
+            CompilationStatus::Failure(_) => d.draw_text(
                 "Compilation failed!",
                 cursor.x as _,
                 cursor.y as _,
@@ -328,49 +443,491 @@ impl Level {
             ),
             CompilationStatus::None => (),
         }
+        cursor.y += 35.0;
+        self.console.draw(d, &self.code, cursor);
+
+        // * Inventory
+        let mut y = 10;
+        for (index, token) in self.inventory.iter().enumerate() {
+            let width = measure_text(token.token(), font_size);
+            let x = SCREEN_SIZE.0 as i32 - width - 15;
+            if index == self.selected_item {
+                d.draw_rectangle_lines(x - 5, y - 3, width + 10, font_size + 6, Color::RED);
+            }
+            if token.rejected > 0.0 {
+                d.draw_text(
+                    token.token(),
+                    x + token.shake() as i32,
+                    y,
+                    font_size,
+                    Color::GRAY,
+                );
+            } else {
+                d.draw_text(token.token(), x, y, font_size, Color::RED);
+            }
+            if token.format() != token.token() {
+                let shape = token.shape();
+                d.draw_text(
+                    &shape,
+                    x - measure_text(&shape, font_size / 2) - 15,
+                    y + font_size / 4,
+                    font_size / 2,
+                    Color::GRAY,
+                );
+            }
+            y += font_size + 10;
+        }
     }
 
     // * -------------------------------------------------------------------------------- Recompile ------------------------------------------------------------------------------- * //
     pub fn recompile(&mut self) {
         self.status = compile(&self.code);
-        self.cursor_target_position = None;
+        self.loop_reports = analyze(&self.code);
+        self.console.compiled(&self.code, &self.status);
+        self.selected_slot = self.selected_slot.min(self.slot_count().saturating_sub(1));
+    }
+
+    // * ------------------------------------------------------------------------------- Checkpoints ------------------------------------------------------------------------------ * //
+    /// Drops the checkpoint and program output, which the solver doesn't need in every state it keeps
+    pub fn forget_history(&mut self) {
+        self.checkpoint = None;
+        self.console.clear_output();
+    }
+
+    fn save_checkpoint(&mut self, player: &Player) {
+        self.checkpoint = None;
+        let mut level = self.clone();
+        level.sounds.clear();
+        self.checkpoint = Some(Checkpoint {
+            level: Box::new(level),
+            player: player.clone(),
+        });
+    }
+
+    /// Level and player as they were after the last token was placed, or at the spawn before the first one
+    pub fn restart(&self) -> Option<(Level, Player)> {
+        let checkpoint = self.checkpoint.as_ref()?;
+        let mut level = (*checkpoint.level).clone();
+        level.checkpoint = Some(checkpoint.clone());
+        level.show_hints = self.show_hints;
+        level.files = self.files.clone();
+        let mut player = checkpoint.player.clone();
+        player.velocity = Vector2::zero();
+        player.revive();
+        Some((level, player))
+    }
+
+    // * --------------------------------------------------------------------------------- Slots ---------------------------------------------------------------------------------- * //
+    pub fn slot_count(&self) -> usize {
+        self.code.matches('$').count()
+    }
+
+    pub fn selected_slot(&self) -> usize {
+        self.selected_slot
+    }
+
+    pub fn select_slot(&mut self, slot: usize) {
+        self.selected_slot = slot.min(self.slot_count().saturating_sub(1));
+    }
+
+    /// Declared kind of a slot, or the one inferred from the code around it
+    pub fn slot_kind(&self, slot: usize) -> SlotKind {
+        if let Some(Some(kind)) = self.declared_slots.get(slot) {
+            return *kind;
+        }
+        match self.code.match_indices('$').nth(slot) {
+            Some((offset, _)) => slot_kind(&self.code, offset),
+            None => SlotKind::Statement,
+        }
+    }
+
+    pub fn fits_anywhere(&self, kind: TokenKind) -> bool {
+        (0..self.slot_count()).any(|slot| kind.fits(self.slot_kind(slot)))
+    }
+
+    /// Slot a collected token should go into and where it's drawn: the selected slot if the token fits there,
+    /// otherwise the next one it fits. `None` when it fits nowhere
+    pub fn insertion_target(&self, kind: TokenKind) -> Option<(usize, Vector2)> {
+        let count = self.slot_count();
+        let slot = (0..count)
+            .map(|offset| (self.selected_slot + offset) % count)
+            .find(|&slot| kind.fits(self.slot_kind(slot)))?;
+        self.slot_position(slot).map(|position| (slot, position))
+    }
+
+    /// Left edge and vertical center of a slot in the code view
+    fn slot_position(&self, slot: usize) -> Option<Vector2> {
+        let (offset, _) = self.code.match_indices('$').nth(slot)?;
+        Some(self.code_position(offset))
+    }
+
+    /// Where a byte of the code is drawn, at the vertical center of its line. Laid out the same way [`Level::draw_hud`] does
+    fn code_position(&self, offset: usize) -> Vector2 {
+        let font_size = 20;
+        let cursor_width = (font_size as f32 * 0.6) as i32;
+        let line_start = self.code[..offset].rfind('\n').map_or(0, |index| index + 1);
+        let line = self.code[..offset].matches('\n').count();
+        let prefix = &self.code[line_start..offset];
+
+        let advance = prefix
+            .split('$')
+            .map(|part| text_width(part, font_size))
+            .sum::<i32>()
+            + prefix.matches('$').count() as i32 * cursor_width;
+        rvec2(12 + advance, 30 + line as i32 * font_size + font_size / 2)
+    }
+
+    /// Slot under a point in the code view
+    fn slot_at(&self, point: Vector2) -> Option<usize> {
+        let font_size = 20.0;
+        (0..self.slot_count())
+            .filter_map(|slot| {
+                let center = self.slot_position(slot)? + rvec2(font_size * 0.3, 0);
+                Some((slot, (center - point).length()))
+            })
+            .filter(|&(_, distance)| distance < font_size)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(slot, _)| slot)
+    }
+
+    /// Puts a token straight into a slot, without flying there
+    pub fn fill(&mut self, slot: usize, token: &Token) -> bool {
+        let mut token = token.clone();
+        token.slot = slot;
+        self.place(&token)
+    }
+
+    /// Puts a token into its slot, keeping the selection and tokens in flight aimed at the same slots.
+    /// If the slot doesn't take this kind of token any more, it goes to the next one that does.
+    /// Returns false if there is none
+    fn place(&mut self, token: &Token) -> bool {
+        let slot = if token.slot < self.slot_count() && token.kind.fits(self.slot_kind(token.slot))
+        {
+            token.slot
+        } else {
+            match self.insertion_target(token.kind) {
+                Some((slot, _)) => slot,
+                None => return false,
+            }
+        };
+        let offset = match self.code.match_indices('$').nth(slot) {
+            Some((offset, _)) => offset,
+            None => return false,
+        };
+        let text = token.format();
+        self.code.replace_range(offset..offset + 1, text);
+
+        let added = text.matches('$').count();
+        let slot_kind = self
+            .declared_slots
+            .splice(slot..slot + 1, vec![None; added])
+            .next()
+            .flatten();
+        self.remap_slots(|other| {
+            if other > slot {
+                other + added - 1
+            } else {
+                other
+            }
+        });
+        for placed in &mut self.placed {
+            if placed.span.start > offset {
+                placed.span.start += text.len() - 1;
+            }
+            if placed.span.end > offset {
+                placed.span.end += text.len() - 1;
+            }
+        }
+        self.placed.push(PlacedToken {
+            token: token.clone(),
+            span: Span {
+                start: offset,
+                end: offset + text.len(),
+            },
+            slot_kind,
+        });
+        self.recompile();
+        true
+    }
+
+    /// Takes a placed token out of the code, restoring its slot. The token, and any tokens placed inside it, drop back into the world
+    pub fn eject(&mut self, index: usize) {
+        let span = self.placed[index].span;
+        let restored_kind = self.placed[index].slot_kind;
+        let position = self.code_position(span.start) + self.camera.view();
+        let slot = self.code[..span.start].matches('$').count();
+        let inner_slots = self.code[span.start..span.end].matches('$').count();
+        let removed = span.end - span.start - 1;
+
+        let mut dropped = Vec::new();
+        self.placed.retain(|placed| {
+            let inside = span.start <= placed.span.start && placed.span.end <= span.end;
+            if inside {
+                dropped.push(placed.token.clone());
+            }
+            !inside
+        });
+        for placed in &mut self.placed {
+            if placed.span.start >= span.end {
+                placed.span.start -= removed;
+            }
+            if placed.span.end >= span.end {
+                placed.span.end -= removed;
+            }
+        }
+        self.code.replace_range(span.start..span.end, "$");
+        self.declared_slots.drain(slot..slot + inner_slots);
+        self.declared_slots.insert(slot, restored_kind);
+        self.remap_slots(|other| {
+            if other < slot {
+                other
+            } else if other < slot + inner_slots {
+                slot
+            } else {
+                other + 1 - inner_slots
+            }
+        });
+
+        let font_size = 20;
+        for (index, token) in dropped.into_iter().enumerate() {
+            let width = text_width(&token.token, font_size);
+            self.drop_token(
+                token,
+                position + rvec2(width / 2 + index as i32 * font_size, font_size / 2),
+            );
+        }
+        self.sounds.push(SoundEffect::PickToken);
+        self.recompile();
+    }
+
+    /// Puts a token back into the world, falling from `position`
+    fn drop_token(&mut self, mut token: Token, position: Vector2) {
+        token.position = position;
+        token.target = None;
+        token.finished = false;
+        token.falling = true;
+        self.tokens.push(token);
+    }
+
+    /// Placed token under a point in the code view, the innermost one if they are nested
+    fn placed_at(&self, point: Vector2) -> Option<usize> {
+        let font_size = 20.0;
+        self.placed
+            .iter()
+            .enumerate()
+            .filter(|(_, placed)| {
+                let start = self.code_position(placed.span.start);
+                let end = self.code_position(placed.span.end);
+                start.y == end.y
+                    && (start.x..end.x).contains(&point.x)
+                    && (point.y - start.y).abs() < font_size / 2.0
+            })
+            .min_by_key(|(_, placed)| placed.span.end - placed.span.start)
+            .map(|(index, _)| index)
+    }
+
+    // * -------------------------------------------------------------------------------- Inventory ------------------------------------------------------------------------------- * //
+    pub fn inventory(&self) -> &[Token] {
+        &self.inventory
+    }
+
+    /// Takes a token lying in the world into the inventory
+    pub fn pick_up(&mut self, index: usize) {
+        let token = self.tokens.remove(index);
+        self.inventory.push(token);
+        self.sounds.push(SoundEffect::PickToken);
     }
 
-    pub fn platforms(&self) -> &Vec<Rectangle> {
+    /// Sends a token from the inventory into the selected slot, or the next one it fits.
+    /// Returns false if there is no slot to put it in
+    pub fn insert(&mut self, item: usize) -> bool {
+        let target = match self.inventory.get(item) {
+            Some(token) => self.insertion_target(token.kind),
+            None => return false,
+        };
+        if target.is_none() {
+            self.inventory[item].reject();
+            return false;
+        }
+        let mut token = self.inventory.remove(item);
+        token.collect(target);
+        self.tokens.push(token);
+        self.selected_item = self
+            .selected_item
+            .min(self.inventory.len().saturating_sub(1));
+        true
+    }
+
+    fn remap_slots(&mut self, remap: impl Fn(usize) -> usize) {
+        self.selected_slot = remap(self.selected_slot);
+        for token in &mut self.tokens {
+            token.slot = remap(token.slot);
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn status(&self) -> &CompilationStatus {
+        &self.status
+    }
+
+    pub fn world_size(&self) -> Vector2 {
+        self.world_size
+    }
+
+    pub fn camera(&self) -> &FollowCamera {
+        &self.camera
+    }
+
+    pub fn platforms(&self) -> &Vec<Platform> {
         &self.platforms
     }
 
+    /// Platform `rect` is standing on
+    pub fn platform_under(&self, rect: &Rectangle) -> Option<&Platform> {
+        self.platforms
+            .iter()
+            .find(|platform| platform.supports(rect))
+    }
+
+    pub fn enemies(&self) -> &[Enemy] {
+        &self.enemies
+    }
+
     pub fn index(&self) -> usize {
         self.index
     }
+
+    /// Level file and C source this level was loaded from
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn report_error(&mut self, error: &Error) {
+        self.console.error(format!("{:#}", error));
+    }
 }
 
-#[derive(Clone)]
+/// What a token turns into once it's placed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenKind {
+    /// A complete statement, like `break;`
+    Statement,
+    /// A value, like `player_is_jumping()`
+    Expression,
+    /// A keyword that expands into a template with slots of its own, like `if`
+    Keyword,
+    /// A `{ $ }` block
+    Block,
+}
+
+/// Built-in templates, `$` marks the slots a token adds to the code
+const TEMPLATES: &[(&str, TokenKind, &str)] = &[
+    ("if", TokenKind::Keyword, "if ($) $"),
+    ("else", TokenKind::Keyword, "else $"),
+    ("while", TokenKind::Keyword, "while ($) { $ }"),
+    ("for", TokenKind::Keyword, "for (;;$) $"),
+    ("!", TokenKind::Expression, "!($)"),
+    ("{}", TokenKind::Block, "{ $ }"),
+];
+
+impl TokenKind {
+    /// Expressions go where a value is expected, everything else is a statement
+    pub fn fits(self, slot: SlotKind) -> bool {
+        match self {
+            Self::Expression => slot == SlotKind::Expression,
+            Self::Statement | Self::Keyword | Self::Block => slot == SlotKind::Statement,
+        }
+    }
+
+    /// Guesses the kind of a token from its text
+    pub fn infer(text: &str) -> Self {
+        if let Some(&(_, kind, _)) = TEMPLATES.iter().find(|(name, ..)| *name == text) {
+            kind
+        } else if text.ends_with(';') || text.ends_with('}') {
+            Self::Statement
+        } else {
+            Self::Expression
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Token {
-    token: &'static str,
+    token: String,
+    kind: TokenKind,
+    /// Text the token is expanded to in the code
+    template: String,
     position: Vector2,
     font_size: i32,
     finished: bool,
     target: Option<Vector2>,
+    /// Slot the token was aimed at when it was picked up
+    slot: usize,
+    /// Ejected tokens fall until they land on a platform
+    falling: bool,
+    /// Shakes for a moment after being touched when it doesn't fit any slot
+    rejected: f32,
 }
 
 impl Token {
-    pub fn new(token: &'static str, position: Vector2) -> Self {
+    /// Token with its kind and template inferred from the text, `if` becomes `if ($) $`
+    pub fn new(token: &str, position: Vector2) -> Self {
+        let template = TEMPLATES
+            .iter()
+            .find(|(name, ..)| *name == token)
+            .map_or(token, |&(_, _, template)| template);
         Self {
-            token,
+            token: token.to_owned(),
+            kind: TokenKind::infer(token),
+            template: template.to_owned(),
             font_size: 20,
             position,
             finished: false,
             target: None,
+            slot: 0,
+            falling: false,
+            rejected: 0.0,
         }
     }
 
-    pub fn update(&mut self, rl: &mut RaylibHandle) {
+    pub fn with_kind(mut self, kind: TokenKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_template(mut self, template: &str) -> Self {
+        self.template = template.to_owned();
+        self
+    }
+
+    /// Falls onto platforms or the `floor`, or flies to its target. Targets are slots on the screen,
+    /// `view` is where the screen is in the world
+    pub fn update(&mut self, frame_time: f32, platforms: &[Platform], floor: f32, view: Vector2) {
+        self.rejected = (self.rejected - frame_time).max(0.0);
+        if self.falling && self.target.is_none() {
+            let previous_bottom = self.position.y;
+            self.position.y += 400.0 * frame_time;
+            let rect = self.rect();
+            if let Some(platform) = platforms
+                .iter()
+                .find(|platform| platform.blocks(&rect, previous_bottom))
+            {
+                self.position.y = platform.rect().y;
+                self.falling = false;
+            } else if self.position.y >= floor {
+                self.position.y = floor;
+                self.falling = false;
+            }
+        }
         if let Some(target) = self.target {
-            let target =
-                target + rvec2(measure_text(self.token, self.font_size), self.font_size) / 2.0;
+            let target = view
+                + target
+                + rvec2(text_width(&self.token, self.font_size), self.font_size) / 2.0;
             let distance = (target - self.position).length();
-            let velocity = (target - self.position) / distance * 2000.0 * rl.get_frame_time();
+            let velocity = (target - self.position) / distance * 2000.0 * frame_time;
             self.position += if velocity.length() > distance {
                 self.finished = true;
                 velocity.normalized() * distance
@@ -382,36 +939,67 @@ impl Token {
 
     pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
         d.draw_text(
-            self.token,
-            self.position.x as i32 - measure_text(self.token, self.font_size) / 2,
+            &self.token,
+            self.position.x as i32 - text_width(&self.token, self.font_size) / 2
+                + self.shake() as i32,
             self.position.y as i32 - self.font_size,
             self.font_size,
-            Color::RED,
+            if self.rejected > 0.0 {
+                Color::GRAY
+            } else {
+                Color::RED
+            },
         );
     }
 
-    pub fn collect(&mut self, assets: &mut Assets, target: Option<Vector2>) {
-        if self.target.is_none() {
-            if let Some(target) = target {
-                self.target = Some(target);
-                assets.audio.play_sound(&assets.pick_token_sound)
+    /// Feedback for touching a token that can't go anywhere
+    pub fn reject(&mut self) {
+        self.rejected = 0.5;
+    }
+
+    fn shake(&self) -> f32 {
+        (self.rejected * 60.0).sin() * 3.0 * self.rejected / 0.5
+    }
+
+    /// Sends the token flying towards a slot from [`Level::insertion_target`], returns true if it was picked up just now
+    pub fn collect(&mut self, target: Option<(usize, Vector2)>) -> bool {
+        match (self.target, target) {
+            (None, Some((slot, position))) => {
+                self.slot = slot;
+                self.target = Some(position);
+                true
             }
+            _ => false,
         }
     }
 
-    pub fn token(&self) -> &'static str {
-        self.token
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Text that goes into the code, with `$` for any slots it adds
+    pub fn format(&self) -> &str {
+        &self.template
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        self.kind
     }
 
-    pub fn format(&self) -> &'static str {
-        match self.token {
-            "if" => "if ($) $",
-            token => token,
+    /// The template with its slots named by what they expect, like `if (<expression>) <statement>`
+    pub fn shape(&self) -> String {
+        let mut shape = String::new();
+        let mut last = 0;
+        for (offset, _) in self.template.match_indices('$') {
+            shape += &self.template[last..offset];
+            shape += &format!("<{}>", slot_kind(&self.template, offset).name());
+            last = offset + 1;
         }
+        shape + &self.template[last..]
     }
 
     pub fn rect(&self) -> Rectangle {
-        let size = rvec2(measure_text(self.token, self.font_size), self.font_size);
+        let size = rvec2(text_width(&self.token, self.font_size), self.font_size);
         let tl = self.position - size * rvec2(0.5, 1);
         rrect(tl.x, tl.y, size.x, size.y)
     }
@@ -423,25 +1011,83 @@ impl Token {
     pub fn finished(&self) -> bool {
         self.finished
     }
+
+    /// On its way into the code
+    pub fn flying(&self) -> bool {
+        self.target.is_some()
+    }
+}
+
+/// How an enemy moves, set per enemy in the level file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EnemyBehavior {
+    /// Walks back and forth at this speed, turning around at walls
+    Patrol(f32),
+    /// Moves along with the player
+    MirrorPlayer,
+    /// Walks towards the player at this speed
+    Chase(f32),
+    /// Keeps jumping in place with this velocity
+    Jumper(f32),
+    Stationary,
+    /// Flies through the points in a loop, ignoring walls
+    FollowPath {
+        speed: f32,
+        points: Vec<(f32, f32)>,
+    },
+}
+
+impl Default for EnemyBehavior {
+    fn default() -> Self {
+        Self::Patrol(120.0)
+    }
+}
+
+impl EnemyBehavior {
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Patrol(_) => Color::RED,
+            Self::MirrorPlayer => Color::PURPLE,
+            Self::Chase(_) => Color::MAROON,
+            Self::Jumper(_) => Color::ORANGE,
+            Self::Stationary => Color::DARKGRAY,
+            Self::FollowPath { .. } => Color::PINK,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Enemy {
     position: Vector2,
+    velocity: Vector2,
     direction: f32,
     size: Vector2,
+    behavior: EnemyBehavior,
+    /// Path point the enemy is flying to
+    path_target: usize,
 }
 
 impl Enemy {
+    const GRAVITY: f32 = 2000.0;
+
     pub fn new(position: Vector2) -> Self {
         Self {
             position,
+            velocity: Vector2::zero(),
             direction: 1.0,
             size: rvec2(60, 80),
+            behavior: EnemyBehavior::default(),
+            path_target: 0,
         }
     }
 
-    fn collides(&self, level: &Level) -> bool {
+    pub fn with_behavior(mut self, behavior: EnemyBehavior) -> Self {
+        self.behavior = behavior;
+        self
+    }
+
+    /// Whether the enemy is in a wall, `previous_bottom` is where its bottom was before moving
+    fn collides(&self, level: &Level, previous_bottom: f32) -> bool {
         let rect = rrect(
             self.position.x + 1.0,
             self.position.y - self.size.y + 1.0,
@@ -449,14 +1095,14 @@ impl Enemy {
             self.size.y - 2.0,
         );
         if rect.x < 0.0
-            || rect.x + rect.width >= 800.0
+            || rect.x + rect.width >= level.world_size.x
             || rect.y < 0.0
-            || rect.y + rect.height >= 800.0
+            || rect.y + rect.height >= level.world_size.y
         {
             return true;
         } else {
             for platform in &level.platforms {
-                if platform.check_collision_recs(&rect) {
+                if platform.blocks(&rect, previous_bottom) {
                     return true;
                 }
             }
@@ -464,46 +1110,107 @@ impl Enemy {
         false
     }
 
-    pub fn update(
-        &mut self,
-        rl: &mut RaylibHandle,
-        assets: &mut Assets,
-        player: &Player,
-        level: &mut Level,
-    ) {
-        if level.index >= 7 {
-            self.position.x += player.velocity.x * rl.get_frame_time();
-            while self.collides(level) {
-                self.position.x -= player.velocity.x.signum() * 0.5;
+    /// Moves along one axis and backs out of walls, returns true if it hit one
+    fn slide(&mut self, delta: Vector2, level: &Level) -> bool {
+        if delta == Vector2::zero() {
+            return false;
+        }
+        let previous_bottom = self.position.y;
+        self.position += delta;
+        // `signum` of 0 is 1, so only the axis that moved backs out
+        let back = |delta: f32| {
+            if delta != 0.0 {
+                delta.signum() * 0.5
+            } else {
+                0.0
             }
-            self.position.y += player.velocity.y * rl.get_frame_time();
-            while self.collides(level) {
-                self.position.y -= player.velocity.y.signum() * 0.5;
+        };
+        let step = rvec2(back(delta.x), back(delta.y));
+        let mut hit = false;
+        while self.collides(level, previous_bottom) {
+            self.position -= step;
+            hit = true;
+        }
+        hit
+    }
+
+    pub fn update(&mut self, frame_time: f32, player: &Player, level: &mut Level) {
+        match self.behavior {
+            EnemyBehavior::Patrol(speed) => {
+                if self.slide(rvec2(self.direction * speed * frame_time, 0), level) {
+                    self.direction *= -1.0;
+                }
             }
-        } else {
-            self.position.x += self.direction * 120.0 * rl.get_frame_time();
-            if self.collides(level) {
-                self.direction *= -1.0;
+            EnemyBehavior::MirrorPlayer => {
+                self.slide(rvec2(player.velocity.x * frame_time, 0), level);
+                self.slide(rvec2(0, player.velocity.y * frame_time), level);
+            }
+            EnemyBehavior::Chase(speed) => {
+                let distance =
+                    player.position.x + player.size.x / 2.0 - self.position.x - self.size.x / 2.0;
+                let step = (speed * frame_time).min(distance.abs()) * distance.signum();
+                self.slide(rvec2(step, 0), level);
+            }
+            EnemyBehavior::Jumper(jump_velocity) => {
+                self.velocity.y += Self::GRAVITY * frame_time;
+                if self.slide(rvec2(0, self.velocity.y * frame_time), level) {
+                    self.velocity.y = if self.velocity.y > 0.0 {
+                        -jump_velocity
+                    } else {
+                        0.0
+                    };
+                }
+            }
+            EnemyBehavior::Stationary => (),
+            EnemyBehavior::FollowPath { speed, ref points } => {
+                if let Some(&(x, y)) = points.get(self.path_target) {
+                    let offset = rvec2(x, y) - self.position;
+                    let step = speed * frame_time;
+                    if offset.length() <= step {
+                        self.position = rvec2(x, y);
+                        self.path_target = (self.path_target + 1) % points.len();
+                    } else {
+                        self.position += offset.normalized() * step;
+                    }
+                }
             }
         }
-        let rect = rrect(
+        let rect = self.rect();
+        let targets = level
+            .tokens
+            .iter()
+            .map(|token| level.insertion_target(token.kind))
+            .collect::<Vec<_>>();
+        for (token, target) in level.tokens.iter_mut().zip(targets) {
+            if !token.rect().check_collision_recs(&rect) {
+                continue;
+            }
+            if token.collect(target) {
+                level.sounds.push(SoundEffect::PickToken);
+            } else if target.is_none() && !token.flying() {
+                token.reject();
+            }
+        }
+    }
+
+    pub fn position(&self) -> Vector2 {
+        self.position
+    }
+
+    pub fn rect(&self) -> Rectangle {
+        rrect(
             self.position.x,
             self.position.y - self.size.y,
             self.size.x,
             self.size.y,
-        );
-        for token in &mut level.tokens {
-            if token.rect().check_collision_recs(&rect) {
-                token.collect(assets, level.cursor_target_position);
-            }
-        }
+        )
     }
 
     pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
         d.draw_rectangle_v(
             self.position - self.size * rvec2(0, 1),
             self.size,
-            Color::RED,
+            self.behavior.color(),
         );
     }
 }
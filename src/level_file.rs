@@ -0,0 +1,473 @@
+use crate::analysis::SlotKind;
+use crate::assets::*;
+use crate::camera::SCREEN_SIZE;
+use crate::level::*;
+use crate::platform::*;
+use crate::player::*;
+use crate::tilemap::TileMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const LEVELS_DIRECTORY: &str = "levels";
+const MANIFEST: &str = "manifest.ron";
+
+/// Level order, as stored in `levels/manifest.ron`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub chapters: Vec<ChapterFile>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChapterFile {
+    pub name: String,
+    pub levels: Vec<String>,
+}
+
+/// Named run of consecutive levels
+#[derive(Clone, Debug)]
+pub struct Chapter {
+    pub name: String,
+    pub levels: Range<usize>,
+}
+
+/// All level files in play order, grouped into chapters
+#[derive(Clone, Debug)]
+pub struct Campaign {
+    pub levels: Vec<PathBuf>,
+    pub chapters: Vec<Chapter>,
+}
+
+/// Finds all level files, in the order the manifest lists them.
+/// Levels missing from the manifest go last, sorted by name
+pub fn scan_levels() -> Result<Vec<PathBuf>> {
+    Ok(scan_campaign()?.levels)
+}
+
+/// Reads the chapters from the manifest. Levels missing from it go into an extra chapter at the end
+pub fn scan_campaign() -> Result<Campaign> {
+    let directory = Path::new(LEVELS_DIRECTORY);
+    let manifest_path = directory.join(MANIFEST);
+    let text = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let manifest: Manifest =
+        ron::from_str(&text).map_err(|err| anyhow!("{}:{}", manifest_path.display(), err))?;
+
+    let mut levels = Vec::new();
+    let mut chapters = Vec::new();
+    for chapter in &manifest.chapters {
+        let start = levels.len();
+        for name in &chapter.levels {
+            let path = directory.join(name);
+            if !path.is_file() {
+                bail!("{} lists a missing level {}", manifest_path.display(), name);
+            }
+            if levels.contains(&path) {
+                bail!("{} lists level {} twice", manifest_path.display(), name);
+            }
+            levels.push(path);
+        }
+        chapters.push(Chapter {
+            name: chapter.name.clone(),
+            levels: start..levels.len(),
+        });
+    }
+
+    let mut unlisted = std::fs::read_dir(directory)
+        .with_context(|| format!("Failed to scan {}", directory.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "ron")
+                && path.file_name().map_or(false, |name| name != MANIFEST)
+                && !levels.contains(path)
+        })
+        .collect::<Vec<_>>();
+    if !unlisted.is_empty() {
+        unlisted.sort();
+        chapters.push(Chapter {
+            name: String::from("Extras"),
+            levels: levels.len()..levels.len() + unlisted.len(),
+        });
+        levels.extend(unlisted);
+    }
+    Ok(Campaign { levels, chapters })
+}
+
+/// Turns 1-based level numbers from the command line into indices, all levels when there are none
+pub fn select_levels(args: &[String], levels: &[PathBuf]) -> Result<Vec<usize>> {
+    if args.is_empty() {
+        return Ok((0..levels.len()).collect());
+    }
+    args.iter()
+        .map(|arg| {
+            arg.parse::<usize>()
+                .ok()
+                .filter(|number| (1..=levels.len()).contains(number))
+                .map(|number| number - 1)
+                .with_context(|| format!("No level {}", arg))
+        })
+        .collect()
+}
+
+/// Polls modification times of level files, for hot reloading
+pub struct LevelWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    timer: f32,
+}
+
+impl LevelWatcher {
+    const POLL_INTERVAL: f32 = 0.5;
+
+    pub fn new(files: &[PathBuf]) -> Self {
+        Self {
+            files: files
+                .iter()
+                .map(|path| (path.clone(), modified(path)))
+                .collect(),
+            timer: 0.0,
+        }
+    }
+
+    pub fn changed(&mut self, frame_time: f32) -> bool {
+        self.timer += frame_time;
+        if self.timer < Self::POLL_INTERVAL {
+            return false;
+        }
+        self.timer = 0.0;
+
+        let mut changed = false;
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Level description, as stored in `levels/*.ron`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelFile {
+    /// C source with `$` slots, relative to the level file
+    pub code: String,
+    /// Top-left corner of the player
+    pub spawn: (f32, f32),
+    /// Width and height, the screen scrolls to follow the player when it's bigger than the window
+    #[serde(default = "default_world_size")]
+    pub world_size: (f32, f32),
+    #[serde(default)]
+    pub platforms: Vec<(f32, f32, f32, f32)>,
+    /// Platforms that do more than stand in the way, plain ones go into `platforms`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub special_platforms: Vec<PlatformFile>,
+    /// Ground drawn as ASCII art, merged into platforms when the level loads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tiles: Option<TileMap>,
+    #[serde(default)]
+    pub tokens: Vec<TokenFile>,
+    #[serde(default)]
+    pub enemies: Vec<EnemyFile>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub globals: BTreeMap<String, GlobalFile>,
+    /// Kinds of `$` slots by number, counting from 1. Slots not listed are inferred from the code around them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub slots: BTreeMap<usize, SlotKind>,
+    /// Enemies still steal tokens, but touching them doesn't kill. Levels 5-10 set it: they were made
+    /// before contact damage, around enemies carrying tokens into the code, and weren't solved with it
+    #[serde(default)]
+    pub friendly_enemies: bool,
+    #[serde(default)]
+    pub physics: Physics,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformFile {
+    pub rect: (f32, f32, f32, f32),
+    #[serde(default)]
+    pub kind: PlatformKind,
+    /// Function the level code calls to show or hide the platform
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub switch: Option<Switch>,
+}
+
+impl PlatformFile {
+    pub fn build(&self) -> Platform {
+        let (x, y, width, height) = self.rect;
+        let platform = Platform::new(rrect(x, y, width, height), self.kind.clone());
+        match &self.switch {
+            Some(switch) => platform.with_switch(switch.clone()),
+            None => platform,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenFile {
+    pub text: String,
+    pub position: (f32, f32),
+    /// Inferred from the text when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<TokenKind>,
+    /// What the token expands to in the code, `$` adds slots. Defaults to the built-in template or the text itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnemyFile {
+    /// Bottom-left corner of the enemy
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub behavior: EnemyBehavior,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlobalFile {
+    pub value: i64,
+    #[serde(default)]
+    pub per_frame: i64,
+}
+
+fn default_world_size() -> (f32, f32) {
+    SCREEN_SIZE
+}
+
+impl LevelFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read level file {}", path.display()))?;
+        let file: Self =
+            ron::from_str(&text).map_err(|err| anyhow!("{}:{}", path.display(), err))?;
+        file.validate()
+            .with_context(|| format!("Invalid level file {}", path.display()))?;
+        Ok(file)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (index, &(_, _, width, height)) in self.platforms.iter().enumerate() {
+            if width <= 0.0 || height <= 0.0 {
+                bail!("platform #{} has a non-positive size", index + 1);
+            }
+        }
+        for (index, platform) in self.special_platforms.iter().enumerate() {
+            let (_, _, width, height) = platform.rect;
+            if width <= 0.0 || height <= 0.0 {
+                bail!("special platform #{} has a non-positive size", index + 1);
+            }
+            if let PlatformKind::Moving { points, .. } = &platform.kind {
+                if points.is_empty() {
+                    bail!("special platform #{} has an empty path", index + 1);
+                }
+            }
+            if let Some(switch) = &platform.switch {
+                let function = switch.function();
+                if function.is_empty()
+                    || function.starts_with(|c: char| c.is_ascii_digit())
+                    || !function
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    bail!(
+                        "special platform #{} is switched by `{}`, which isn't a function name",
+                        index + 1,
+                        function
+                    );
+                }
+            }
+        }
+        if let Some(tiles) = &self.tiles {
+            if tiles.tile_size <= 0.0 {
+                bail!("tile size must be positive");
+            }
+            for (row, line) in tiles.rows.iter().enumerate() {
+                if let Some(tile) = line.chars().find(|c| !"#-^. ".contains(*c)) {
+                    bail!("tile row {} has an unknown tile `{}`", row + 1, tile);
+                }
+            }
+        }
+        for (index, enemy) in self.enemies.iter().enumerate() {
+            if let EnemyBehavior::FollowPath { points, .. } = &enemy.behavior {
+                if points.is_empty() {
+                    bail!("enemy #{} has an empty path", index + 1);
+                }
+            }
+        }
+        for (index, token) in self.tokens.iter().enumerate() {
+            if token.text.trim().is_empty() {
+                bail!("token #{} has no text", index + 1);
+            }
+            if token
+                .template
+                .as_ref()
+                .map_or(false, |template| template.trim().is_empty())
+            {
+                bail!("token #{} has an empty template", index + 1);
+            }
+        }
+        if self.world_size.0 <= 0.0 || self.world_size.1 <= 0.0 {
+            bail!("world size must be positive");
+        }
+        if self.physics.size.0 <= 0.0 || self.physics.size.1 <= 0.0 {
+            bail!("player size must be positive");
+        }
+        Ok(())
+    }
+
+    /// Path to the C source of a level loaded from `path`
+    pub fn code_path(&self, path: &Path) -> PathBuf {
+        path.parent().unwrap_or(Path::new("")).join(&self.code)
+    }
+
+    pub fn load_code(&self, path: &Path) -> Result<String> {
+        let code_path = self.code_path(path);
+        let code = std::fs::read_to_string(&code_path)
+            .with_context(|| format!("Failed to read level code {}", code_path.display()))?;
+        self.check_code(&code, path)?;
+        Ok(code)
+    }
+
+    /// Checks that the code fits the level file loaded from `path`
+    pub fn check_code(&self, code: &str, path: &Path) -> Result<()> {
+        let code_path = self.code_path(path);
+        let slots = code.matches('$').count();
+        for &slot in self.slots.keys() {
+            if slot == 0 || slot > slots {
+                bail!(
+                    "{} declares slot {}, but {} only has {} slots",
+                    path.display(),
+                    slot,
+                    code_path.display(),
+                    slots
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build(&self, code: String) -> (Level, Player) {
+        let mut level = Level::new(
+            code,
+            self.platforms
+                .iter()
+                .map(|&(x, y, width, height)| {
+                    Platform::new(rrect(x, y, width, height), PlatformKind::Solid)
+                })
+                .chain(self.special_platforms.iter().map(PlatformFile::build))
+                .collect(),
+            self.tokens
+                .iter()
+                .map(|token| {
+                    let mut built =
+                        Token::new(&token.text, rvec2(token.position.0, token.position.1));
+                    if let Some(kind) = token.kind {
+                        built = built.with_kind(kind);
+                    }
+                    if let Some(template) = &token.template {
+                        built = built.with_template(template);
+                    }
+                    built
+                })
+                .collect(),
+            self.enemies
+                .iter()
+                .map(|enemy| {
+                    Enemy::new(rvec2(enemy.position.0, enemy.position.1))
+                        .with_behavior(enemy.behavior.clone())
+                })
+                .collect(),
+        );
+        for (name, global) in &self.globals {
+            level = level.with_global(name, global.value, global.per_frame);
+        }
+        if let Some(tiles) = &self.tiles {
+            level = level.with_tiles(tiles.clone());
+        }
+        level = level.with_world_size(rvec2(self.world_size.0, self.world_size.1));
+        if self.friendly_enemies {
+            level = level.with_friendly_enemies();
+        }
+        for (&slot, &kind) in &self.slots {
+            if let Some(slot) = slot.checked_sub(1) {
+                level = level.with_slot_kind(slot, kind);
+            }
+        }
+        let player = Player::new(rvec2(self.spawn.0, self.spawn.1), self.physics.clone());
+        (level, player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(fields: &str) -> Result<()> {
+        let file: LevelFile = ron::from_str(&format!(
+            "(code: \"code1.c\", spawn: (10, 719), {})",
+            fields
+        ))?;
+        file.validate()
+    }
+
+    fn error(fields: &str) -> String {
+        validate(fields).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn valid_level_passes() {
+        validate(
+            "platforms: [(600, 670, 200, 70)], \
+             tokens: [(text: \"break;\", position: (740, 800))], \
+             tiles: Some((rows: [\"..-\", \"#^#\"]))",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid_levels_are_explained() {
+        assert_eq!(
+            error("platforms: [(0, 0, -5, 10)]"),
+            "platform #1 has a non-positive size"
+        );
+        assert_eq!(
+            error(
+                "tokens: [(text: \"break;\", position: (0, 0)), (text: \" \", position: (0, 0))]"
+            ),
+            "token #2 has no text"
+        );
+        assert_eq!(
+            error("tiles: Some((rows: [\"#x#\"]))"),
+            "tile row 1 has an unknown tile `x`"
+        );
+        assert_eq!(error("world_size: (0, 800)"), "world size must be positive");
+    }
+
+    #[test]
+    fn declared_slots_must_be_in_the_code() {
+        let file: LevelFile =
+            ron::from_str("(code: \"code1.c\", spawn: (0, 0), slots: {2: Expression})").unwrap();
+        let path = Path::new("levels/level1.ron");
+        file.check_code("if ($) $", path).unwrap();
+        assert_eq!(
+            file.check_code("$", path).unwrap_err().to_string(),
+            "levels/level1.ron declares slot 2, but levels/code1.c only has 1 slots"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use crate::assets::*;
+use crate::level_file::Chapter;
+use crate::progress::Progress;
+use std::path::PathBuf;
+
+/// Menu listing chapters and their levels, the ones that are unlocked can be replayed
+pub struct LevelSelect {
+    selected: usize,
+    /// Why the last level couldn't be opened
+    error: Option<String>,
+}
+
+impl LevelSelect {
+    const TILE_SIZE: f32 = 80.0;
+    const TILE_GAP: f32 = 20.0;
+    const TILES_PER_ROW: usize = 7;
+    const MARGIN: f32 = 50.0;
+    const HEADER_SIZE: i32 = 30;
+
+    /// Starts on the first level that isn't completed yet, or endless mode once they all are
+    pub fn new(levels: &[PathBuf], progress: &Progress) -> Self {
+        Self {
+            selected: (0..levels.len())
+                .find(|&index| !progress.completed(&levels[index]))
+                .unwrap_or(levels.len()),
+            error: None,
+        }
+    }
+
+    pub fn select(&mut self, level: usize) {
+        self.selected = level;
+    }
+
+    /// Shown until the next level is picked
+    pub fn report_error(&mut self, error: &Error) {
+        self.error = Some(format!("{:#}", error));
+    }
+
+    /// Returns the level to play once one is picked, `levels.len()` is endless mode
+    pub fn update(
+        &mut self,
+        rl: &RaylibHandle,
+        chapters: &[Chapter],
+        levels: &[PathBuf],
+        progress: &Progress,
+    ) -> Option<usize> {
+        let tiles = Self::layout(chapters).1;
+        let mouse = rl.get_mouse_position();
+        if let Some(hovered) = tiles
+            .iter()
+            .position(|tile| tile.check_collision_point_rec(mouse))
+        {
+            if rl.get_mouse_delta() != Vector2::zero() {
+                self.selected = hovered;
+            }
+            if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT)
+                && progress.unlocked(levels, hovered)
+            {
+                self.error = None;
+                return Some(hovered);
+            }
+        }
+
+        let mut step = |step: isize| {
+            let target = self.selected as isize + step;
+            if (0..=levels.len() as isize).contains(&target) {
+                self.selected = target as usize;
+            }
+        };
+        if rl.is_key_pressed(KeyboardKey::KEY_RIGHT) || rl.is_key_pressed(KeyboardKey::KEY_D) {
+            step(1);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_LEFT) || rl.is_key_pressed(KeyboardKey::KEY_A) {
+            step(-1);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_DOWN) || rl.is_key_pressed(KeyboardKey::KEY_S) {
+            step(Self::TILES_PER_ROW as _);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_UP) || rl.is_key_pressed(KeyboardKey::KEY_W) {
+            step(-(Self::TILES_PER_ROW as isize));
+        }
+
+        let confirmed =
+            rl.is_key_pressed(KeyboardKey::KEY_ENTER) || rl.is_key_pressed(KeyboardKey::KEY_SPACE);
+        if confirmed && progress.unlocked(levels, self.selected) {
+            self.error = None;
+            return Some(self.selected);
+        }
+        None
+    }
+
+    /// Chapter headers with their height on screen, and a tile for every level.
+    /// Endless mode gets a tile right after the last level
+    fn layout(chapters: &[Chapter]) -> (Vec<(&str, f32)>, Vec<Rectangle>) {
+        let mut headers = Vec::new();
+        let mut tiles = Vec::new();
+        let mut y = 100.0;
+        for (index, chapter) in chapters.iter().enumerate() {
+            headers.push((chapter.name.as_str(), y));
+            y += Self::HEADER_SIZE as f32 + 10.0;
+            let count = chapter.levels.len() + usize::from(index + 1 == chapters.len());
+            for column in 0..count {
+                if column > 0 && column % Self::TILES_PER_ROW == 0 {
+                    y += Self::TILE_SIZE + Self::TILE_GAP;
+                }
+                tiles.push(rrect(
+                    Self::MARGIN
+                        + (column % Self::TILES_PER_ROW) as f32
+                            * (Self::TILE_SIZE + Self::TILE_GAP),
+                    y,
+                    Self::TILE_SIZE,
+                    Self::TILE_SIZE,
+                ));
+            }
+            y += Self::TILE_SIZE + Self::TILE_GAP;
+        }
+        (headers, tiles)
+    }
+
+    pub fn draw<D: RaylibDraw>(
+        &self,
+        d: &mut D,
+        chapters: &[Chapter],
+        levels: &[PathBuf],
+        progress: &Progress,
+    ) {
+        d.clear_background(Color::RAYWHITE);
+        d.draw_text("Select a level", Self::MARGIN as _, 30, 40, Color::BLACK);
+
+        let (headers, tiles) = Self::layout(chapters);
+        for (name, y) in headers {
+            d.draw_text(
+                name,
+                Self::MARGIN as _,
+                y as _,
+                Self::HEADER_SIZE,
+                Color::DARKGRAY,
+            );
+        }
+        for (index, tile) in tiles.into_iter().enumerate() {
+            let unlocked = progress.unlocked(levels, index);
+            let best_time = levels
+                .get(index)
+                .and_then(|level| progress.best_time(level));
+            let (background, foreground) = if best_time.is_some() {
+                (Color::LIME, Color::WHITE)
+            } else if unlocked {
+                (Color::WHITE, Color::BLACK)
+            } else {
+                (Color::LIGHTGRAY, Color::GRAY)
+            };
+            d.draw_rectangle_rec(tile, background);
+            if index == self.selected {
+                d.draw_rectangle_lines_ex(tile, 4.0, Color::ORANGE);
+            } else {
+                d.draw_rectangle_lines_ex(tile, 2.0, Color::DARKGRAY);
+            }
+
+            let number = if index == levels.len() {
+                String::from("?")
+            } else {
+                (index + 1).to_string()
+            };
+            d.draw_text(
+                &number,
+                (tile.x + tile.width / 2.0) as i32 - text_width(&number, 40) / 2,
+                tile.y as i32 + 10,
+                40,
+                foreground,
+            );
+            let caption = match best_time {
+                Some(time) => format_time(time),
+                None if !unlocked => String::from("locked"),
+                None if index == levels.len() => String::from("endless"),
+                None => String::new(),
+            };
+            d.draw_text(
+                &caption,
+                (tile.x + tile.width / 2.0) as i32 - text_width(&caption, 15) / 2,
+                (tile.y + tile.height) as i32 - 22,
+                15,
+                foreground,
+            );
+        }
+
+        if let Some(error) = &self.error {
+            d.draw_text(error, 10, 800 - 55, 15, Color::RED);
+        }
+        d.draw_text(
+            "Arrows/WASD or mouse - choose, Enter or click - play, Esc in a level - back here",
+            10,
+            800 - 30,
+            15,
+            Color::GRAY,
+        );
+    }
+}
+
+fn format_time(time: f32) -> String {
+    format!("{}:{:0>5.2}", (time / 60.0) as u32, time % 60.0)
+}
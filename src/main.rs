@@ -1,14 +1,30 @@
 #![windows_subsystem = "windows"]
 
+pub mod analysis;
 pub mod assets;
+pub mod camera;
+pub mod console;
+pub mod diagnostics;
+pub mod editor;
+pub mod generator;
 pub mod language;
 pub mod level;
+pub mod level_file;
+pub mod level_select;
+pub mod platform;
 pub mod player;
+pub mod progress;
+pub mod rewind;
+pub mod solver;
+pub mod tilemap;
+pub mod validation;
 
 use assets::*;
 
 pub enum GameState {
+    LevelSelect,
     Playing,
+    Editing,
     LevelTransition {
         level: usize,
         timer: f32,
@@ -26,13 +42,73 @@ impl GameState {
     }
 }
 
+/// Solver states an endless level generation searches each frame, small enough to keep the game running
+const GENERATION_STATES_PER_FRAME: usize = 20;
+
+/// Key bindings shown by the F1 overlay
+const CONTROLS: &[(&str, &str)] = &[
+    ("A / D", "Move"),
+    ("Space", "Jump / Double jump"),
+    ("F", "Put a picked up token in"),
+    ("Tab", "Choose which token"),
+    ("Q / E or click", "Choose a slot"),
+    ("Backspace or right click", "Take a token back out"),
+    ("Z (hold)", "Rewind"),
+    ("R", "Back to the last placed token"),
+    ("Shift + R", "Restart the level"),
+    ("H", "Hint"),
+    ("`", "Show or hide the console"),
+    ("F2", "Level editor"),
+    ("Esc", "Level select"),
+];
+
+fn draw_controls<D: RaylibDraw>(d: &mut D) {
+    let font_size = 20;
+    let top = 400 - (CONTROLS.len() as i32 + 2) * font_size / 2;
+    d.draw_rectangle(0, 0, 800, 800, Color::WHITE.fade(0.9));
+    d.draw_text("Controls", 100, top, 30, Color::BLACK);
+    for (index, (keys, action)) in CONTROLS.iter().enumerate() {
+        let y = top + (index as i32 + 2) * font_size;
+        d.draw_text(keys, 100, y, font_size, Color::RED);
+        d.draw_text(action, 380, y, font_size, Color::DARKGRAY);
+    }
+}
+
 fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("--solve") {
+        return solver::run(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("--validate") {
+        return validation::run(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("--generate") {
+        return generator::run(&args[1..]);
+    }
+
     let (mut rl, thread) = raylib::init().size(800, 800).title("Break a loop!").build();
+    rl.set_exit_key(None);
 
     let mut assets = Assets::load(&mut rl, &thread).context("Failed to load assets!")?;
 
-    let (mut level, mut player) = level::Level::load(0).context("Failed to load first level!")?;
-    let mut state = GameState::Playing;
+    let level_file::Campaign { levels, chapters } =
+        level_file::scan_campaign().context("Failed to find levels!")?;
+    let mut progress = progress::Progress::load().context("Failed to load progress!")?;
+    let mut level_select = level_select::LevelSelect::new(&levels, &progress);
+    let (mut level, mut player) = level::Level::load(&levels, 0)
+        .context("Failed to load first level!")?
+        .context("No levels found!")?;
+    let mut watcher = level_file::LevelWatcher::new(level.files());
+    let mut editor: Option<editor::Editor> = None;
+    let mut rewind = rewind::Rewind::default();
+    let mut state = GameState::LevelSelect;
+    // Seed of the first generated level, levels past the hand-made ones are generated while it's set
+    let mut endless: Option<u64> = None;
+    // Next endless level, searched for a bit every frame while the previous one is played
+    let mut generation: Option<generator::Generation> = None;
+    let mut level_time = 0.0;
+    let mut deaths = 0;
+    let mut show_controls = false;
     let level_transition_time = 0.5;
 
     {
@@ -101,10 +177,81 @@ fn main() -> Result<()> {
             return Ok(());
         }
 
-        // * Levels
-        if rl.is_key_pressed(KeyboardKey::KEY_R) {
-            state = GameState::transition(level.index());
-            assets.audio.play_sound(&assets.restart_level_sound);
+        // * Editor
+        // A running transition would be dropped along with the level it's about to load
+        if rl.is_key_pressed(KeyboardKey::KEY_F2)
+            && !matches!(
+                state,
+                GameState::LevelSelect | GameState::LevelTransition { .. }
+            )
+        {
+            rl.set_target_fps(i32::MAX as u32);
+            if let (GameState::Editing, Some(editor)) = (&state, &mut editor) {
+                match editor.play_test() {
+                    Result::Ok(test) => {
+                        let show_hints = level.show_hints;
+                        (level, player) = test;
+                        level.show_hints = show_hints;
+                        watcher = level_file::LevelWatcher::new(level.files());
+                        rewind.clear();
+                        state = GameState::Playing;
+                    }
+                    Err(err) => editor.report_error(&err),
+                }
+            } else {
+                if editor
+                    .as_ref()
+                    .map_or(true, |editor| editor.index() != level.index())
+                {
+                    match editor::Editor::open(&levels, level.index()) {
+                        Result::Ok(opened) => editor = Some(opened),
+                        Err(err) => level.report_error(&err),
+                    }
+                }
+                if editor.is_some() {
+                    state = GameState::Editing;
+                }
+            }
+        }
+
+        if !matches!(state, GameState::Editing | GameState::LevelSelect) {
+            // * Levels
+            if rl.is_key_pressed(KeyboardKey::KEY_R) {
+                let hard_restart = rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+                    || rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+                match level.restart() {
+                    Some(checkpoint) if !hard_restart => {
+                        (level, player) = checkpoint;
+                        rewind.clear();
+                    }
+                    _ => state = GameState::transition(level.index()),
+                }
+                assets.audio.play_sound(&assets.restart_level_sound);
+            }
+            if rl.is_key_pressed(KeyboardKey::KEY_H) {
+                level.show_hints = !level.show_hints;
+            }
+            if rl.is_key_pressed(KeyboardKey::KEY_F1) {
+                show_controls = !show_controls;
+            }
+            if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) {
+                rl.set_target_fps(i32::MAX as u32);
+                level_select.select(level.index().min(levels.len()));
+                state = GameState::LevelSelect;
+            }
+
+            // * Hot reload
+            if watcher.changed(rl.get_frame_time()) {
+                if let Err(err) = level.reload(&levels, &mut player) {
+                    level.report_error(&err);
+                }
+                watcher = level_file::LevelWatcher::new(level.files());
+                rewind.clear();
+            }
+        }
+
+        if let Some(generation) = &mut generation {
+            generation.advance(GENERATION_STATES_PER_FRAME);
         }
 
         if let GameState::LevelTransition {
@@ -116,10 +263,55 @@ fn main() -> Result<()> {
             *timer += rl.get_frame_time();
             if *timer >= level_transition_time / 2.0 && !*loaded {
                 rl.set_target_fps(i32::MAX as u32);
-                if let Some(next_level) = level::Level::load(*next_level) {
-                    (level, player) = next_level;
-                } else {
-                    break;
+                let next = match endless {
+                    Some(seed) if *next_level >= levels.len() => {
+                        let start = |index: usize| {
+                            let seed = seed.wrapping_add((index - levels.len()) as u64);
+                            generator::Generation::new(seed, index)
+                        };
+                        if generation
+                            .as_ref()
+                            .map_or(true, |generation| generation.index() != *next_level)
+                        {
+                            generation = Some(start(*next_level));
+                        }
+                        if generation
+                            .as_ref()
+                            .map_or(false, generator::Generation::done)
+                        {
+                            let generated =
+                                generation.take().and_then(generator::Generation::finish);
+                            generation = Some(start(*next_level + 1));
+                            generated.map(|generated| generated.map(Some))
+                        } else {
+                            // The screen stays covered until the solver finds a level
+                            *timer = level_transition_time / 2.0;
+                            None
+                        }
+                    }
+                    _ => Some(level::Level::load(&levels, *next_level)),
+                };
+                if let Some(next) = next {
+                    *loaded = true;
+                    match next {
+                        Result::Ok(Some(next_level)) => {
+                            let show_hints = level.show_hints;
+                            (level, player) = next_level;
+                            level.show_hints = show_hints;
+                            watcher = level_file::LevelWatcher::new(level.files());
+                            rewind.clear();
+                            level_time = 0.0;
+                        }
+                        Result::Ok(None) => break,
+                        // Like hot reload, a broken level file or an unsolvable seed shouldn't close the game
+                        Err(err) => {
+                            level.report_error(&err);
+                            level_select.report_error(&err);
+                            level_select.select((*next_level).min(levels.len()));
+                            state = GameState::LevelSelect;
+                            continue;
+                        }
+                    }
                 }
             }
             if *timer >= level_transition_time {
@@ -129,20 +321,89 @@ fn main() -> Result<()> {
         }
 
         // * Update
-        if let GameState::Playing = state {
-            if level.update(&mut rl, &mut assets, &mut player) {
+        if let (GameState::Editing, Some(editor)) = (&state, &mut editor) {
+            editor.update(&mut rl);
+        }
+        if let GameState::LevelSelect = state {
+            if let Some(index) = level_select.update(&rl, &chapters, &levels, &progress) {
+                endless = (index == levels.len()).then(|| {
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |time| time.as_secs())
+                });
+                generation = None;
+                state = GameState::transition(index);
+                assets.audio.play_sound(&assets.play_button_click_sound);
+            }
+        }
+        let rewinding = matches!(state, GameState::Playing) && rl.is_key_down(KeyboardKey::KEY_Z);
+        if rewinding {
+            if let Some(snapshot) = rewind.step_back(rl.get_frame_time()) {
+                let show_hints = level.show_hints;
+                (level, player) = snapshot;
+                level.show_hints = show_hints;
+                level.sounds.clear();
+            }
+        } else if let GameState::Playing = state {
+            let frame_time = rl.get_frame_time();
+            level_time += frame_time;
+            if level.update(&mut rl, &mut player) {
+                if let Some(path) = levels.get(level.index()) {
+                    progress.complete(path, level_time);
+                    if let Err(err) = progress.save() {
+                        level.report_error(&err);
+                    }
+                }
                 state = GameState::transition(level.index() + 1);
                 assets.audio.play_sound(&assets.next_level_sound);
             }
 
-            player.update(&mut rl, &mut assets, &mut level);
+            player.update(player::Input::read(&rl), frame_time, &mut level);
+            for sound in level.sounds.drain(..) {
+                assets.play(sound);
+            }
+            if player.respawn_due() {
+                deaths += 1;
+                match level.restart() {
+                    Some(checkpoint) => {
+                        (level, player) = checkpoint;
+                        rewind.clear();
+                    }
+                    None => state = GameState::transition(level.index()),
+                }
+            }
+            rewind.record(frame_time, &level, &player);
         }
 
         // * Render
         let screen_size = rvec2(rl.get_screen_width(), rl.get_screen_height());
         let mut d = rl.begin_drawing(&thread);
-        level.draw(&mut d);
-        player.draw(&mut d);
+        if let (GameState::Editing, Some(editor)) = (&state, &editor) {
+            editor.draw(&mut d, &assets.tileset_texture);
+        } else if let GameState::LevelSelect = state {
+            level_select.draw(&mut d, &chapters, &levels, &progress);
+        } else {
+            {
+                let mut world = d.begin_mode2D(level.camera().camera_2d());
+                level.draw_world(&mut world, &assets.tileset_texture);
+                player.draw(&mut world);
+            }
+            level.draw_hud(&mut d);
+            if show_controls {
+                draw_controls(&mut d);
+            }
+        }
+        if rewinding {
+            d.draw_rectangle(0, 0, 800, 800, Color::BLUE.fade(0.1));
+            let text = format!("<< Rewinding ({:.1}s left)", rewind.available());
+            d.draw_text(
+                &text,
+                400 - measure_text(&text, 30) / 2,
+                40,
+                30,
+                Color::DARKBLUE,
+            );
+        }
 
         if let GameState::LevelTransition { timer, .. } = &mut state {
             let tilt = 200.0;
@@ -157,9 +418,28 @@ fn main() -> Result<()> {
                 Color::BLACK,
             );
         }
+        if let GameState::LevelTransition {
+            timer,
+            loaded: false,
+            ..
+        } = state
+        {
+            if timer >= level_transition_time / 2.0 {
+                let text = "Generating level...";
+                d.draw_text(
+                    text,
+                    400 - measure_text(text, 30) / 2,
+                    385,
+                    30,
+                    Color::RAYWHITE,
+                );
+            }
+        }
 
-        let time = start.elapsed();
-        d.draw_text(&format_time(time), 10, 10, 20, Color::BLACK)
+        if !matches!(state, GameState::LevelSelect) {
+            let time = start.elapsed();
+            d.draw_text(&format_time(time), 10, 10, 20, Color::BLACK)
+        }
     }
 
     let time = start.elapsed();
@@ -201,17 +481,24 @@ fn main() -> Result<()> {
         );
         center_text(
             &mut d,
-            "Made for Underground Game Jam #1",
+            &format!("Deaths: {}", deaths),
             320,
-            30,
+            40,
             (timer - 4.0).clamp(0.0, 1.0),
         );
+        center_text(
+            &mut d,
+            "Made for Underground Game Jam #1",
+            380,
+            30,
+            (timer - 5.0).clamp(0.0, 1.0),
+        );
         center_text(
             &mut d,
             "By InfiniteCoder",
-            350,
+            410,
             40,
-            (timer - 5.0).clamp(0.0, 1.0),
+            (timer - 6.0).clamp(0.0, 1.0),
         );
     }
 
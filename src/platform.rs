@@ -0,0 +1,284 @@
+use crate::assets::*;
+use serde::{Deserialize, Serialize};
+
+/// What a platform does besides standing in the way
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum PlatformKind {
+    #[default]
+    Solid,
+    /// Can be jumped through from below and stood on from above
+    OneWay,
+    /// Solid, but kills the player on touch
+    Spikes,
+    /// Loops through the points (top-left corners) at this speed, carrying the player standing on it
+    Moving { speed: f32, points: Vec<(f32, f32)> },
+    /// Falls apart this many seconds after being stepped on, comes back a bit later
+    Crumbling(f32),
+    /// Launches the player landing on it with this velocity
+    Bouncy(f32),
+    /// Pushes the player standing on it sideways at this speed
+    Conveyor(f32),
+}
+
+/// Ties a platform to a function the level program can call
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Switch {
+    /// Only there while the function was called in the last loop iteration
+    ShownBy(String),
+    /// Like a door, gone while the function was called in the last loop iteration
+    HiddenBy(String),
+}
+
+impl Switch {
+    pub fn function(&self) -> &str {
+        match self {
+            Self::ShownBy(function) | Self::HiddenBy(function) => function,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Platform {
+    rect: Rectangle,
+    kind: PlatformKind,
+    switch: Option<Switch>,
+    /// Turned off by its switch
+    switched_off: bool,
+    /// Point a moving platform heads to
+    path_target: usize,
+    /// Seconds since a crumbling platform was stepped on
+    crumbling: Option<f32>,
+    /// Seconds until a crumbled platform comes back, it's not there until then
+    crumbled: f32,
+    /// Animation time, for conveyor belts
+    timer: f32,
+    /// Part of a tile map, which draws it instead
+    tiled: bool,
+}
+
+impl Platform {
+    const RESTORE_TIME: f32 = 3.0;
+
+    pub fn new(rect: Rectangle, kind: PlatformKind) -> Self {
+        Self {
+            rect,
+            kind,
+            switch: None,
+            switched_off: false,
+            path_target: 0,
+            crumbling: None,
+            crumbled: 0.0,
+            timer: 0.0,
+            tiled: false,
+        }
+    }
+
+    /// Leaves drawing to the tile map the platform came from
+    pub fn drawn_by_tiles(mut self) -> Self {
+        self.tiled = true;
+        self
+    }
+
+    /// Before the program runs, nothing was called yet
+    pub fn with_switch(mut self, switch: Switch) -> Self {
+        self.switched_off = matches!(switch, Switch::ShownBy(_));
+        self.switch = Some(switch);
+        self
+    }
+
+    pub fn switch(&self) -> Option<&Switch> {
+        self.switch.as_ref()
+    }
+
+    /// Shows or hides a switched platform, given the functions called in the last loop iteration
+    pub fn react(&mut self, called: &[String]) {
+        self.switched_off = match &self.switch {
+            Some(Switch::ShownBy(function)) => !called.contains(function),
+            Some(Switch::HiddenBy(function)) => called.contains(function),
+            None => false,
+        };
+    }
+
+    /// Moves and crumbles the platform, returns how far it moved
+    pub fn update(&mut self, frame_time: f32) -> Vector2 {
+        self.timer += frame_time;
+        self.crumbled = (self.crumbled - frame_time).max(0.0);
+        match &self.kind {
+            PlatformKind::Moving { speed, points } => {
+                let start = rvec2(self.rect.x, self.rect.y);
+                if let Some(&(x, y)) = points.get(self.path_target) {
+                    let offset = rvec2(x, y) - start;
+                    let step = speed * frame_time;
+                    if offset.length() <= step {
+                        self.rect.x = x;
+                        self.rect.y = y;
+                        self.path_target = (self.path_target + 1) % points.len();
+                    } else {
+                        let moved = offset.normalized() * step;
+                        self.rect.x += moved.x;
+                        self.rect.y += moved.y;
+                    }
+                }
+                rvec2(self.rect.x, self.rect.y) - start
+            }
+            &PlatformKind::Crumbling(delay) => {
+                if let Some(timer) = &mut self.crumbling {
+                    *timer += frame_time;
+                    if *timer >= delay {
+                        self.crumbling = None;
+                        self.crumbled = Self::RESTORE_TIME;
+                    }
+                }
+                Vector2::zero()
+            }
+            _ => Vector2::zero(),
+        }
+    }
+
+    /// Starts crumbling, if the platform does that
+    pub fn step_on(&mut self) {
+        if matches!(self.kind, PlatformKind::Crumbling(_))
+            && self.crumbling.is_none()
+            && !self.crumbled()
+        {
+            self.crumbling = Some(0.0);
+        }
+    }
+
+    pub fn crumbled(&self) -> bool {
+        self.crumbled > 0.0
+    }
+
+    /// Not crumbled or switched off
+    pub fn present(&self) -> bool {
+        !self.crumbled() && !self.switched_off
+    }
+
+    /// Whether `rect` can't overlap the platform. One-way platforms only stop things whose bottom was
+    /// above them before moving
+    pub fn blocks(&self, rect: &Rectangle, previous_bottom: f32) -> bool {
+        if !self.present() || !self.rect.check_collision_recs(rect) {
+            return false;
+        }
+        match self.kind {
+            PlatformKind::OneWay => previous_bottom <= self.rect.y + 0.5,
+            _ => true,
+        }
+    }
+
+    /// Whether `rect` is standing on top of the platform
+    pub fn supports(&self, rect: &Rectangle) -> bool {
+        let bottom = rect.y + rect.height;
+        self.present()
+            && bottom >= self.rect.y - 1.0
+            && bottom <= self.rect.y + 0.5
+            && rect.x < self.rect.x + self.rect.width
+            && rect.x + rect.width > self.rect.x
+    }
+
+    /// Whether touching the platform from any side is deadly for `rect`
+    pub fn hurts(&self, rect: &Rectangle) -> bool {
+        let touching = rrect(
+            rect.x - 1.0,
+            rect.y - 1.0,
+            rect.width + 2.0,
+            rect.height + 2.0,
+        );
+        self.kind == PlatformKind::Spikes
+            && self.present()
+            && self.rect.check_collision_recs(&touching)
+    }
+
+    pub fn rect(&self) -> Rectangle {
+        self.rect
+    }
+
+    pub fn kind(&self) -> &PlatformKind {
+        &self.kind
+    }
+
+    pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
+        if self.tiled {
+            return;
+        }
+        if self.switched_off {
+            d.draw_rectangle_lines_ex(self.rect, 1.0, Color::GRAY);
+        } else {
+            self.draw_kind(d);
+        }
+        if let Some(switch) = &self.switch {
+            d.draw_text(
+                &format!("{}()", switch.function()),
+                self.rect.x as i32 + 2,
+                self.rect.y as i32 + 2,
+                10,
+                Color::DARKGRAY,
+            );
+        }
+    }
+
+    fn draw_kind<D: RaylibDraw>(&self, d: &mut D) {
+        let rect = self.rect;
+        match self.kind {
+            PlatformKind::Solid => d.draw_rectangle_rec(rect, Color::new(200, 200, 200, 255)),
+            PlatformKind::OneWay => {
+                d.draw_rectangle_rec(rect, Color::new(200, 200, 200, 100));
+                d.draw_rectangle_rec(
+                    rrect(rect.x, rect.y, rect.width, rect.height.min(6.0)),
+                    Color::GRAY,
+                );
+            }
+            PlatformKind::Spikes => {
+                let height = rect.height.min(12.0);
+                d.draw_rectangle_rec(
+                    rrect(rect.x, rect.y + height, rect.width, rect.height - height),
+                    Color::new(200, 200, 200, 255),
+                );
+                let count = (rect.width / 20.0).ceil();
+                let width = rect.width / count;
+                for index in 0..count as i32 {
+                    let x = rect.x + index as f32 * width;
+                    d.draw_triangle(
+                        rvec2(x + width / 2.0, rect.y),
+                        rvec2(x, rect.y + height),
+                        rvec2(x + width, rect.y + height),
+                        Color::MAROON,
+                    );
+                }
+            }
+            PlatformKind::Moving { .. } => d.draw_rectangle_rec(rect, Color::SKYBLUE),
+            PlatformKind::Crumbling(delay) => {
+                if self.crumbled() {
+                    d.draw_rectangle_lines_ex(rect, 1.0, Color::BEIGE);
+                    return;
+                }
+                let progress = self.crumbling.map_or(0.0, |timer| timer / delay);
+                let shake = (self.timer * 60.0).sin() * 2.0 * progress;
+                d.draw_rectangle_rec(
+                    rrect(rect.x + shake, rect.y, rect.width, rect.height),
+                    Color::BEIGE.fade(1.0 - progress * 0.5),
+                );
+            }
+            PlatformKind::Bouncy(_) => {
+                d.draw_rectangle_rec(rect, Color::new(200, 200, 200, 255));
+                d.draw_rectangle_rec(
+                    rrect(rect.x, rect.y, rect.width, rect.height.min(8.0)),
+                    Color::LIME,
+                );
+            }
+            PlatformKind::Conveyor(speed) => {
+                d.draw_rectangle_rec(rect, Color::DARKGRAY);
+                let arrow = if speed < 0.0 { "<" } else { ">" };
+                let spacing = 20.0;
+                let offset = (self.timer * speed).rem_euclid(spacing);
+                let mut x = rect.x + offset - spacing;
+                while x < rect.x + rect.width {
+                    if x >= rect.x {
+                        d.draw_text(arrow, x as _, rect.y as _, 10, Color::LIGHTGRAY);
+                    }
+                    x += spacing;
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,50 @@
 use crate::assets::*;
+use crate::platform::{Platform, PlatformKind};
+use serde::{Deserialize, Serialize};
+
+/// Per-level tweakable player constants
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Physics {
+    pub size: (f32, f32),
+    pub gravity: f32,
+    pub jump_velocity: f32,
+    /// Horizontal speed, in player widths per second
+    pub speed: f32,
+    pub max_jumps: u32,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self {
+            size: (60.0, 80.0),
+            gravity: 2000.0,
+            jump_velocity: 600.0,
+            speed: 10.0,
+            max_jumps: 2,
+        }
+    }
+}
+
+/// Controls for one frame, read from the keyboard or chosen by the solver
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub jump_pressed: bool,
+    pub jump_released: bool,
+}
+
+impl Input {
+    pub fn read(rl: &RaylibHandle) -> Self {
+        Self {
+            left: rl.is_key_down(KeyboardKey::KEY_A),
+            right: rl.is_key_down(KeyboardKey::KEY_D),
+            jump_pressed: rl.is_key_pressed(KeyboardKey::KEY_SPACE),
+            jump_released: rl.is_key_released(KeyboardKey::KEY_SPACE),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Player {
@@ -7,45 +53,66 @@ pub struct Player {
     pub velocity: Vector2,
     jumps: u32,
     pub max_jumps: u32,
+    pub physics: Physics,
+    /// Seconds since the player died, `None` while alive
+    dying: Option<f32>,
+    /// Bottom edge before the current move, one-way platforms only stop the player coming from above
+    previous_bottom: f32,
 }
 
 impl Player {
-    pub fn new(position: Vector2, size: Vector2) -> Self {
+    /// How long the death animation plays before respawning
+    const DEATH_DURATION: f32 = 0.6;
+
+    pub fn new(position: Vector2, physics: Physics) -> Self {
         Self {
             position,
-            size,
+            size: rvec2(physics.size.0, physics.size.1),
             velocity: Vector2::zero(),
-            jumps: 2,
-            max_jumps: 2,
+            jumps: physics.max_jumps,
+            max_jumps: physics.max_jumps,
+            dying: None,
+            previous_bottom: position.y + physics.size.1,
+            physics,
         }
     }
 
-    pub fn collides(&self, level: &mut crate::level::Level) -> bool {
-        let player_rect = rrect(
+    pub fn default_size(&self) -> Vector2 {
+        rvec2(self.physics.size.0, self.physics.size.1)
+    }
+
+    /// Hitbox, slightly smaller than the player so touching isn't overlapping
+    pub fn rect(&self) -> Rectangle {
+        rrect(
             self.position.x + 0.5,
             self.position.y + 0.5,
             self.size.x - 1.0,
             self.size.y - 1.0,
-        );
+        )
+    }
+
+    pub fn collides(&self, level: &mut crate::level::Level) -> bool {
+        let player_rect = self.rect();
         for platform in level.platforms() {
-            if platform.check_collision_recs(&player_rect) {
+            if platform.blocks(&player_rect, self.previous_bottom) {
                 return true;
             }
         }
         self.position.x < 0.0
             || self.position.y < 0.0
-            || self.position.x + self.size.x >= 800.0
-            || self.position.y + self.size.y >= 800.0
+            || self.position.x + self.size.x >= level.world_size().x
+            || self.position.y + self.size.y >= level.world_size().y
     }
 
     pub fn collidable_move(
         &mut self,
-        rl: &mut RaylibHandle,
+        frame_time: f32,
         level: &mut crate::level::Level,
         direction: Vector2,
     ) {
         let motion = self.velocity * direction;
-        self.position += motion * rl.get_frame_time();
+        self.previous_bottom = self.position.y + self.size.y;
+        self.position += motion * frame_time;
         if self.collides(level) {
             loop {
                 self.position -=
@@ -62,69 +129,155 @@ impl Player {
                 self.velocity.y = 0.0;
                 if direction.y > 0.0 {
                     self.jumps = self.max_jumps;
+                    if let Some(&PlatformKind::Bouncy(velocity)) =
+                        level.platform_under(&self.rect()).map(Platform::kind)
+                    {
+                        self.velocity.y = -velocity;
+                    }
                 }
             }
         }
     }
 
-    pub fn update(
-        &mut self,
-        rl: &mut RaylibHandle,
-        assets: &mut Assets,
-        level: &mut crate::level::Level,
-    ) {
+    pub fn update(&mut self, input: Input, frame_time: f32, level: &mut crate::level::Level) {
+        if let Some(timer) = &mut self.dying {
+            *timer += frame_time;
+            return;
+        }
+
         // * Jump
-        if rl.is_key_pressed(KeyboardKey::KEY_SPACE) && self.jumps > 0 {
+        if input.jump_pressed && self.jumps > 0 {
             self.jumps -= 1;
-            self.velocity.y = -600.0;
-            assets.audio.play_sound(&assets.jump_sound);
+            self.velocity.y = -self.physics.jump_velocity;
+            level.sounds.push(SoundEffect::Jump);
         }
 
-        if rl.is_key_released(KeyboardKey::KEY_SPACE) && self.velocity.y < 0.0 {
+        if input.jump_released && self.velocity.y < 0.0 {
             self.velocity.y *= 0.7;
         }
 
         // Gravity
-        self.velocity.y += 2000.0 * rl.get_frame_time();
+        self.velocity.y += self.physics.gravity * frame_time;
 
         // * Integration
-        let target_velocity = (rl.is_key_down(KeyboardKey::KEY_D) as i32
-            - rl.is_key_down(KeyboardKey::KEY_A) as i32) as f32
-            * self.size.x
-            * 10.0;
+        let mut target_velocity =
+            (input.right as i32 - input.left as i32) as f32 * self.size.x * self.physics.speed;
+        if let Some(&PlatformKind::Conveyor(speed)) =
+            level.platform_under(&self.rect()).map(Platform::kind)
+        {
+            target_velocity += speed;
+        }
 
         self.velocity.x +=
-            (target_velocity - self.velocity.x) * (1.0 - 0.5_f32.powf(rl.get_frame_time() / 0.1));
+            (target_velocity - self.velocity.x) * (1.0 - 0.5_f32.powf(frame_time / 0.1));
 
+        // Platforms that switch on, move or come back can end up inside the player. Out of reach is crushed
+        let unstuck_from = self.position.y;
         while self.collides(level) {
             self.position.y -= 0.5;
+            if unstuck_from - self.position.y > self.size.y {
+                self.position.y = unstuck_from;
+                self.die();
+                level.sounds.push(SoundEffect::Death);
+                return;
+            }
         }
 
-        self.collidable_move(rl, level, rvec2(1, 0));
-        self.collidable_move(rl, level, rvec2(0, 1));
+        self.collidable_move(frame_time, level, rvec2(1, 0));
+        self.collidable_move(frame_time, level, rvec2(0, 1));
 
-        self.check_token_collisions(assets, level);
+        self.check_token_collisions(level);
     }
 
-    fn check_token_collisions(&self, assets: &mut Assets, level: &mut crate::level::Level) {
-        let player_rect = rrect(
-            self.position.x + 0.5,
-            self.position.y + 0.5,
-            self.size.x - 1.0,
-            self.size.y - 1.0,
-        );
-        for token in &mut level.tokens {
-            if token.rect().check_collision_recs(&player_rect) {
-                token.collect(assets, level.cursor_target_position);
+    fn check_token_collisions(&self, level: &mut crate::level::Level) {
+        let player_rect = self.rect();
+        for index in (0..level.tokens.len()).rev() {
+            let token = &level.tokens[index];
+            if !token.flying() && token.rect().check_collision_recs(&player_rect) {
+                if level.fits_anywhere(token.kind()) {
+                    level.pick_up(index);
+                } else {
+                    level.tokens[index].reject();
+                }
             }
         }
     }
 
     pub fn draw<D: RaylibDraw>(&self, d: &mut D) {
-        d.draw_rectangle_v(self.position, self.size, Color::GRAY);
+        match self.dying {
+            Some(timer) => {
+                let progress = (timer / Self::DEATH_DURATION).min(1.0);
+                let size = self.size * (1.0 + progress);
+                d.draw_rectangle_v(
+                    self.position + (self.size - size) / 2.0,
+                    size,
+                    Color::RED.fade(1.0 - progress),
+                );
+            }
+            None => d.draw_rectangle_v(self.position, self.size, Color::GRAY),
+        }
+    }
+
+    pub fn die(&mut self) {
+        if self.dying.is_none() {
+            self.dying = Some(0.0);
+            self.velocity = Vector2::zero();
+        }
+    }
+
+    /// Ends the death animation, for respawning
+    pub fn revive(&mut self) {
+        self.dying = None;
+    }
+
+    pub fn dead(&self) -> bool {
+        self.dying.is_some()
+    }
+
+    /// The death animation is over
+    pub fn respawn_due(&self) -> bool {
+        self.dying
+            .map_or(false, |timer| timer >= Self::DEATH_DURATION)
     }
 
     pub fn jumping(&self) -> bool {
         self.jumps < self.max_jumps
     }
+
+    pub fn jumps(&self) -> u32 {
+        self.jumps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+
+    fn level(platform: Rectangle) -> Level {
+        Level::new(
+            String::new(),
+            vec![Platform::new(platform, PlatformKind::Solid)],
+            Vec::new(),
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn platform_appearing_in_the_player_pushes_them_up() {
+        let mut level = level(rrect(0, 150, 800, 100));
+        let mut player = Player::new(rvec2(100, 100), Physics::default());
+        player.update(Input::default(), 1.0 / 60.0, &mut level);
+        assert!(!player.dead());
+        assert!(player.position.y + player.size.y <= 151.0);
+    }
+
+    #[test]
+    fn platform_reaching_the_top_crushes_the_player() {
+        let mut level = level(rrect(0, 0, 800, 300));
+        let mut player = Player::new(rvec2(100, 100), Physics::default());
+        player.update(Input::default(), 1.0 / 60.0, &mut level);
+        assert!(player.dead());
+        assert_eq!(player.position, rvec2(100, 100));
+    }
 }
@@ -0,0 +1,99 @@
+use crate::assets::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const PROGRESS_FILE: &str = "progress.ron";
+/// Folder in the user's data directory
+const DATA_FOLDER: &str = "break-a-loop";
+
+/// Completed levels and their best times, saved between runs in `progress.ron` in the user's data
+/// directory. Without one, like on the web, progress only lasts until the game closes
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Progress {
+    /// Best time in seconds, by level file name
+    #[serde(default)]
+    pub best_times: BTreeMap<String, f32>,
+}
+
+impl Progress {
+    /// Loads saved progress, or starts fresh if nothing was saved yet
+    pub fn load() -> Result<Self> {
+        let path = match path() {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(Self::default()),
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        ron::from_str(&text).map_err(|err| anyhow!("{}:{}", path.display(), err))
+    }
+
+    /// Writes progress to the data directory, doing nothing when there is none
+    pub fn save(&self) -> Result<()> {
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(folder) = path.parent() {
+            std::fs::create_dir_all(folder)
+                .with_context(|| format!("Failed to create {}", folder.display()))?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())?;
+        std::fs::write(&path, text)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn best_time(&self, level: &Path) -> Option<f32> {
+        self.best_times.get(&key(level)).copied()
+    }
+
+    pub fn completed(&self, level: &Path) -> bool {
+        self.best_times.contains_key(&key(level))
+    }
+
+    /// Levels unlock one after another, the first one is always open
+    pub fn unlocked(&self, levels: &[PathBuf], index: usize) -> bool {
+        index == 0
+            || levels
+                .get(index)
+                .map_or(false, |level| self.completed(level))
+            || levels
+                .get(index - 1)
+                .map_or(false, |previous| self.completed(previous))
+    }
+
+    /// Records a finished level, returns true for a new best time
+    pub fn complete(&mut self, level: &Path, time: f32) -> bool {
+        let best = self.best_times.entry(key(level)).or_insert(f32::INFINITY);
+        if time < *best {
+            *best = time;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Progress is keyed by file name, so it survives reordering levels in the manifest
+fn key(level: &Path) -> String {
+    level
+        .file_name()
+        .unwrap_or(level.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `progress.ron` in the user's data directory: `$XDG_DATA_HOME`, `~/.local/share` or `%APPDATA%`
+fn path() -> Option<PathBuf> {
+    if cfg!(target_os = "emscripten") {
+        return None;
+    }
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let data = var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .or_else(|| var("APPDATA").map(PathBuf::from))?;
+    Some(data.join(DATA_FOLDER).join(PROGRESS_FILE))
+}
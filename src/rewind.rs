@@ -0,0 +1,51 @@
+use crate::level::Level;
+use crate::player::Player;
+use std::collections::VecDeque;
+
+/// Recent snapshots of the level and player, for scrubbing back in time
+#[derive(Default)]
+pub struct Rewind {
+    snapshots: VecDeque<(Level, Player)>,
+    timer: f32,
+}
+
+impl Rewind {
+    const INTERVAL: f32 = 0.1;
+    /// How far back the player can rewind, in seconds
+    const DURATION: f32 = 10.0;
+    /// Rewinding runs this many times faster than the game
+    const SPEED: f32 = 2.0;
+
+    pub fn record(&mut self, frame_time: f32, level: &Level, player: &Player) {
+        self.timer += frame_time;
+        if self.timer < Self::INTERVAL {
+            return;
+        }
+        self.timer = 0.0;
+
+        self.snapshots.push_back((level.clone(), player.clone()));
+        if self.snapshots.len() as f32 > Self::DURATION / Self::INTERVAL {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Steps back while the rewind key is held, returns the snapshot to restore when it's time for the next one
+    pub fn step_back(&mut self, frame_time: f32) -> Option<(Level, Player)> {
+        self.timer += frame_time * Self::SPEED;
+        if self.timer < Self::INTERVAL {
+            return None;
+        }
+        self.timer = 0.0;
+        self.snapshots.pop_back()
+    }
+
+    /// Seconds left to rewind
+    pub fn available(&self) -> f32 {
+        self.snapshots.len() as f32 * Self::INTERVAL
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.timer = 0.0;
+    }
+}
@@ -0,0 +1,410 @@
+use crate::assets::*;
+use crate::level::Level;
+use crate::level_file::{scan_levels, select_levels};
+use crate::player::{Input, Player};
+use std::collections::{HashSet, VecDeque};
+
+/// Simulation step when the game isn't lagging
+const FRAME_TIME: f32 = 1.0 / 60.0;
+/// Frame time after `lagB()`, matching the 10 FPS cap the game sets
+const LAGGING_FRAME_TIME: f32 = 1.0 / 10.0;
+/// Frames every choice of input is held for
+const ACTION_FRAMES: usize = 6;
+/// Frame a short hop lets go of jump on, cutting the jump like releasing space early does
+const RELEASE_FRAME: usize = ACTION_FRAMES / 2;
+/// States closer than this (in pixels, or pixels per second for velocity) count as visited
+const POSITION_GRID: f32 = 8.0;
+const VELOCITY_GRID: f32 = 100.0;
+/// Search limits, levels that need more are reported as unsolvable
+const MAX_STATES: usize = 100_000;
+const MAX_TIME: f32 = 120.0;
+
+/// Input held for [`ACTION_FRAMES`] frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Action {
+    pub direction: i32,
+    pub jump: bool,
+    /// Lets go of jump on [`RELEASE_FRAME`], for a short hop
+    pub release: bool,
+    /// Inventory item put into a `$` slot on the first frame
+    pub insert: Option<(usize, usize)>,
+}
+
+impl Action {
+    const MOVES: [(i32, bool, bool); 9] = [
+        (0, false, false),
+        (-1, false, false),
+        (1, false, false),
+        (0, true, false),
+        (-1, true, false),
+        (1, true, false),
+        (0, true, true),
+        (-1, true, true),
+        (1, true, true),
+    ];
+
+    /// Every move, alone and combined with every way to insert a held token
+    fn all(items: usize, slots: usize) -> impl Iterator<Item = Self> {
+        let inserts = std::iter::once(None)
+            .chain((0..items).flat_map(move |item| (0..slots).map(move |slot| Some((item, slot)))));
+        inserts.flat_map(|insert| {
+            Self::MOVES
+                .iter()
+                .map(move |&(direction, jump, release)| Self {
+                    direction,
+                    jump,
+                    release,
+                    insert,
+                })
+        })
+    }
+
+    fn input(self, frame: usize) -> Input {
+        Input {
+            left: self.direction < 0,
+            right: self.direction > 0,
+            jump_pressed: self.jump && frame == 0,
+            jump_released: self.release && frame == RELEASE_FRAME,
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.direction {
+            0 if self.jump => "",
+            0 => "wait",
+            direction if direction < 0 => "left",
+            _ => "right",
+        };
+        let separator = if self.jump && !direction.is_empty() {
+            "+"
+        } else {
+            ""
+        };
+        let jump = match (self.jump, self.release) {
+            (true, true) => "hop",
+            (true, false) => "jump",
+            _ => "",
+        };
+        write!(f, "{}{}{}", direction, separator, jump)
+    }
+}
+
+/// One line of a solution trace
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub time: f32,
+    pub duration: f32,
+    pub action: Action,
+    /// Held token sent into a slot at the start of this step
+    pub inserted: Option<(String, usize)>,
+    /// Tokens that landed in the code during this step
+    pub placed: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub steps: Vec<Step>,
+    /// Game time until the program exits
+    pub time: f32,
+    /// States searched
+    pub states: usize,
+}
+
+struct Node {
+    parent: Option<usize>,
+    step: Step,
+}
+
+/// Rounded state, two states with the same key are treated as the same
+#[derive(PartialEq, Eq, Hash)]
+struct StateKey {
+    code: String,
+    tokens: Vec<(i32, i32)>,
+    enemies: Vec<(i32, i32)>,
+    /// Moving platforms and whether crumbling and switched ones are there
+    platforms: Vec<(i32, i32, bool)>,
+    player: [i32; 5],
+    jumps: (u32, u32),
+    inventory: Vec<String>,
+    slot: usize,
+}
+
+impl StateKey {
+    fn new(level: &Level, player: &Player) -> Self {
+        let grid = |value: f32, grid: f32| (value / grid).round() as i32;
+        Self {
+            code: level.code().to_owned(),
+            tokens: level
+                .tokens
+                .iter()
+                .map(|token| {
+                    let rect = token.rect();
+                    (grid(rect.x, POSITION_GRID), grid(rect.y, POSITION_GRID))
+                })
+                .collect(),
+            enemies: level
+                .enemies()
+                .iter()
+                .map(|enemy| {
+                    let position = enemy.position();
+                    (
+                        grid(position.x, POSITION_GRID),
+                        grid(position.y, POSITION_GRID),
+                    )
+                })
+                .collect(),
+            platforms: level
+                .platforms()
+                .iter()
+                .map(|platform| {
+                    let rect = platform.rect();
+                    (
+                        grid(rect.x, POSITION_GRID),
+                        grid(rect.y, POSITION_GRID),
+                        platform.present(),
+                    )
+                })
+                .collect(),
+            player: [
+                grid(player.position.x, POSITION_GRID),
+                grid(player.position.y, POSITION_GRID),
+                grid(player.velocity.x, VELOCITY_GRID),
+                grid(player.velocity.y, VELOCITY_GRID),
+                grid(player.size.x, 1.0),
+            ],
+            jumps: (player.jumps(), player.max_jumps),
+            inventory: level
+                .inventory()
+                .iter()
+                .map(|token| token.token().to_owned())
+                .collect(),
+            slot: level.selected_slot(),
+        }
+    }
+}
+
+/// Breadth-first search over held inputs for a way to make the level program exit, which can run a
+/// few states at a time. Runs the same `Level::step` and `Player::update` the game does, with a fixed frame time
+pub struct Search {
+    nodes: Vec<Node>,
+    visited: HashSet<StateKey>,
+    queue: VecDeque<(Option<usize>, f32, Level, Player)>,
+}
+
+impl Search {
+    pub fn new(level: &Level, player: &Player) -> Self {
+        let mut visited = HashSet::new();
+        visited.insert(StateKey::new(level, player));
+        let mut start = level.clone();
+        start.forget_history();
+        Self {
+            nodes: Vec::new(),
+            visited,
+            queue: VecDeque::from([(None, 0.0, start, player.clone())]),
+        }
+    }
+
+    /// Expands up to `states` queued states, returns the outcome once the search is over
+    pub fn advance(&mut self, states: usize) -> Option<Option<Solution>> {
+        for _ in 0..states {
+            let (parent, time, level, player) = match self.queue.pop_front() {
+                Some(state) => state,
+                None => return Some(None),
+            };
+            if time >= MAX_TIME {
+                continue;
+            }
+            if let Some(outcome) = self.expand(parent, time, &level, &player) {
+                return Some(outcome);
+            }
+        }
+        None
+    }
+
+    fn expand(
+        &mut self,
+        parent: Option<usize>,
+        time: f32,
+        level: &Level,
+        player: &Player,
+    ) -> Option<Option<Solution>> {
+        for action in Action::all(level.inventory().len(), level.slot_count()) {
+            let mut level = level.clone();
+            let mut player = player.clone();
+            let tokens = unplaced_tokens(&level)
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            let inserted = action.insert.map(|(item, slot)| {
+                let token = level.inventory()[item].token().to_owned();
+                level.select_slot(slot);
+                level.insert(item);
+                (token, slot)
+            });
+
+            let mut duration = 0.0;
+            let mut finished = false;
+            for frame in 0..ACTION_FRAMES {
+                let frame_time = if level.lagging {
+                    LAGGING_FRAME_TIME
+                } else {
+                    FRAME_TIME
+                };
+                duration += frame_time;
+                if level.step(frame_time, &mut player) {
+                    finished = true;
+                    break;
+                }
+                player.update(action.input(frame), frame_time, &mut level);
+            }
+            level.sounds.clear();
+            // Dead ends are dropped instead of respawned, so queued states need no checkpoint
+            level.forget_history();
+
+            if !finished && player.dead() {
+                continue;
+            }
+            if !finished && !self.visited.insert(StateKey::new(&level, &player)) {
+                continue;
+            }
+            self.nodes.push(Node {
+                parent,
+                step: Step {
+                    time,
+                    duration,
+                    action,
+                    inserted,
+                    placed: placed_tokens(&tokens, &level),
+                },
+            });
+            if finished {
+                return Some(Some(trace(
+                    &self.nodes,
+                    self.nodes.len() - 1,
+                    self.visited.len(),
+                )));
+            }
+            if self.visited.len() >= MAX_STATES {
+                return Some(None);
+            }
+            self.queue
+                .push_back((Some(self.nodes.len() - 1), time + duration, level, player));
+        }
+        None
+    }
+}
+
+/// Runs a whole [`Search`]
+pub fn solve(level: &Level, player: &Player) -> Option<Solution> {
+    let mut search = Search::new(level, player);
+    loop {
+        if let Some(outcome) = search.advance(usize::MAX) {
+            return outcome;
+        }
+    }
+}
+
+/// Tokens lying around, flying or held
+fn unplaced_tokens(level: &Level) -> impl Iterator<Item = &str> {
+    level
+        .tokens
+        .iter()
+        .chain(level.inventory())
+        .map(|token| token.token())
+}
+
+/// Tokens from `before` that are now part of the code
+fn placed_tokens(before: &[String], level: &Level) -> Vec<String> {
+    let mut remaining = unplaced_tokens(level).collect::<Vec<_>>();
+    before
+        .iter()
+        .filter(
+            |text| match remaining.iter().position(|token| token == text) {
+                Some(index) => {
+                    remaining.remove(index);
+                    false
+                }
+                None => true,
+            },
+        )
+        .cloned()
+        .collect()
+}
+
+/// Walks back from the finishing node, merging runs of the same held input
+fn trace(nodes: &[Node], last: usize, states: usize) -> Solution {
+    let mut path = Vec::new();
+    let mut index = Some(last);
+    while let Some(node) = index.map(|index| &nodes[index]) {
+        path.push(node.step.clone());
+        index = node.parent;
+    }
+    path.reverse();
+
+    let time = path.last().map_or(0.0, |step| step.time + step.duration);
+    let mut steps = Vec::<Step>::new();
+    for step in path {
+        match steps.last_mut() {
+            Some(last)
+                if last.action == step.action
+                    && !step.action.jump
+                    && step.inserted.is_none()
+                    && last.placed.is_empty() =>
+            {
+                last.duration += step.duration;
+                last.placed = step.placed;
+            }
+            _ => steps.push(step),
+        }
+    }
+    Solution {
+        steps,
+        time,
+        states,
+    }
+}
+
+/// `--solve [level numbers...]`: proves the levels can be finished, all of them by default
+pub fn run(args: &[String]) -> Result<()> {
+    let levels = scan_levels().context("Failed to find levels!")?;
+    let indices = select_levels(args, &levels)?;
+
+    let mut unsolved = Vec::new();
+    for index in indices {
+        let (level, player) = Level::load(&levels, index)?.context("Level was removed")?;
+        let name = format!("Level {} ({})", index + 1, levels[index].display());
+        let solution = match solve(&level, &player) {
+            Some(solution) => solution,
+            None => {
+                println!("{}: no solution found", name);
+                unsolved.push(index + 1);
+                continue;
+            }
+        };
+
+        println!(
+            "{}: solved in {:.2}s, {} states searched",
+            name, solution.time, solution.states
+        );
+        for step in &solution.steps {
+            println!(
+                "  {:>6.2}s  {:<10} for {:.2}s",
+                step.time,
+                step.action.to_string(),
+                step.duration
+            );
+            if let Some((token, slot)) = &step.inserted {
+                println!("           insert `{}` into slot {}", token, slot + 1);
+            }
+            for token in &step.placed {
+                println!("           placed `{}`", token);
+            }
+        }
+    }
+
+    if !unsolved.is_empty() {
+        bail!("No solution for levels {:?}", unsolved);
+    }
+    Ok(())
+}
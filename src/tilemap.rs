@@ -0,0 +1,186 @@
+use crate::assets::*;
+use crate::platform::{Platform, PlatformKind};
+use serde::{Deserialize, Serialize};
+
+/// Level geometry drawn as ASCII art, one character per tile: `#` is ground, `-` a one-way platform
+/// and `^` spikes, `.` or a space is empty
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileMap {
+    #[serde(default = "default_tile_size")]
+    pub tile_size: f32,
+    /// Top-left corner of the first tile
+    #[serde(default)]
+    pub origin: (f32, f32),
+    pub rows: Vec<String>,
+}
+
+fn default_tile_size() -> f32 {
+    40.0
+}
+
+/// Tiles of one kind merged into a rectangle, in tiles
+struct Run {
+    kind: char,
+    left: usize,
+    right: usize,
+    top: usize,
+    bottom: usize,
+}
+
+impl TileMap {
+    fn kind(tile: char) -> Option<PlatformKind> {
+        match tile {
+            '#' => Some(PlatformKind::Solid),
+            '-' => Some(PlatformKind::OneWay),
+            '^' => Some(PlatformKind::Spikes),
+            _ => None,
+        }
+    }
+
+    fn grid(&self) -> Vec<Vec<char>> {
+        self.rows.iter().map(|row| row.chars().collect()).collect()
+    }
+
+    fn tile_rect(&self, left: usize, top: usize, width: usize, height: usize) -> Rectangle {
+        rrect(
+            self.origin.0 + left as f32 * self.tile_size,
+            self.origin.1 + top as f32 * self.tile_size,
+            width as f32 * self.tile_size,
+            height as f32 * self.tile_size,
+        )
+    }
+
+    /// Collision rectangles: runs of the same tile in a row, stacked with identical runs below them.
+    /// Ground is drawn by [`TileMap::draw`], the rest draws like any other platform
+    pub fn platforms(&self) -> Vec<Platform> {
+        let mut runs = Vec::<Run>::new();
+        for (row, tiles) in self.grid().iter().enumerate() {
+            let mut column = 0;
+            while column < tiles.len() {
+                let kind = tiles[column];
+                if Self::kind(kind).is_none() {
+                    column += 1;
+                    continue;
+                }
+                let left = column;
+                while column < tiles.len() && tiles[column] == kind {
+                    column += 1;
+                }
+                match runs.iter_mut().find(|run| {
+                    run.kind == kind && run.left == left && run.right == column && run.bottom == row
+                }) {
+                    Some(run) => run.bottom = row + 1,
+                    None => runs.push(Run {
+                        kind,
+                        left,
+                        right: column,
+                        top: row,
+                        bottom: row + 1,
+                    }),
+                }
+            }
+        }
+
+        runs.into_iter()
+            .filter_map(|run| {
+                let kind = Self::kind(run.kind)?;
+                let rect = self.tile_rect(
+                    run.left,
+                    run.top,
+                    run.right - run.left,
+                    run.bottom - run.top,
+                );
+                let platform = Platform::new(rect, kind);
+                Some(if run.kind == '#' {
+                    platform.drawn_by_tiles()
+                } else {
+                    platform
+                })
+            })
+            .collect()
+    }
+
+    /// Draws ground tiles, picking each one from a 4x4 tileset by which of its neighbours are ground
+    /// too: 1 above, 2 right, 4 below and 8 left
+    pub fn draw<D: RaylibDraw>(&self, d: &mut D, tileset: &Texture2D) {
+        let grid = self.grid();
+        let ground = |column: usize, row: usize| {
+            grid.get(row)
+                .and_then(|tiles| tiles.get(column))
+                .map_or(false, |&tile| tile == '#')
+        };
+        let source_size = tileset.width as f32 / 4.0;
+        for (row, tiles) in grid.iter().enumerate() {
+            for column in 0..tiles.len() {
+                if !ground(column, row) {
+                    continue;
+                }
+                let mask = (row > 0 && ground(column, row - 1)) as i32
+                    | (ground(column + 1, row) as i32) << 1
+                    | (ground(column, row + 1) as i32) << 2
+                    | (column > 0 && ground(column - 1, row)) as i32 * 8;
+                d.draw_texture_pro(
+                    tileset,
+                    rrect(
+                        (mask % 4) as f32 * source_size,
+                        (mask / 4) as f32 * source_size,
+                        source_size,
+                        source_size,
+                    ),
+                    self.tile_rect(column, row, 1, 1),
+                    Vector2::zero(),
+                    0.0,
+                    Color::WHITE,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platforms(rows: &[&str]) -> Vec<((f32, f32, f32, f32), PlatformKind)> {
+        let tiles = TileMap {
+            tile_size: 10.0,
+            origin: (5.0, 0.0),
+            rows: rows.iter().map(|&row| row.to_owned()).collect(),
+        };
+        tiles
+            .platforms()
+            .iter()
+            .map(|platform| {
+                let rect = platform.rect();
+                (
+                    (rect.x, rect.y, rect.width, rect.height),
+                    platform.kind().clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn runs_merge_with_identical_runs_below() {
+        assert_eq!(
+            platforms(&["##..", "##--", "^^^^"]),
+            [
+                ((5.0, 0.0, 20.0, 20.0), PlatformKind::Solid),
+                ((25.0, 10.0, 20.0, 10.0), PlatformKind::OneWay),
+                ((5.0, 20.0, 40.0, 10.0), PlatformKind::Spikes),
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_of_different_widths_stay_apart() {
+        assert_eq!(
+            platforms(&["###", "#. "]),
+            [
+                ((5.0, 0.0, 30.0, 10.0), PlatformKind::Solid),
+                ((5.0, 10.0, 10.0, 10.0), PlatformKind::Solid),
+            ]
+        );
+    }
+}
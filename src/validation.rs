@@ -0,0 +1,173 @@
+use crate::assets::*;
+use crate::diagnostics::location;
+use crate::language::CompilationStatus;
+use crate::level::{Level, Token};
+use crate::level_file::{scan_levels, select_levels};
+use crate::player::{Input, Player};
+use std::collections::HashSet;
+
+/// How long the untouched level code has to keep running
+const IDLE_TIME: f32 = 10.0;
+const FRAME_TIME: f32 = 1.0 / 60.0;
+
+/// Checks that a level can't be broken by the order tokens are picked up in.
+/// Returns a description of every problem found
+pub fn validate(level: &Level, player: &Player) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let slots = level.code().matches('$').count()
+        + level
+            .tokens
+            .iter()
+            .map(|token| token.format().matches('$').count())
+            .sum::<usize>();
+    if slots > level.tokens.len() {
+        problems.push(format!(
+            "{} slots to fill, but only {} tokens",
+            slots,
+            level.tokens.len()
+        ));
+    }
+
+    let mut failures = HashSet::new();
+    if let CompilationStatus::Failure(error) = level.status() {
+        failures.insert(level.code().to_owned());
+        problems.push(compile_error(
+            level.code(),
+            &error.message,
+            error.offset,
+            &[],
+        ));
+    }
+    if let Some(time) = finishes_idle(level, player) {
+        problems.push(format!(
+            "the code exits after {:.2}s before any token is placed",
+            time
+        ));
+    }
+
+    try_orders(
+        level,
+        level.tokens.iter().collect(),
+        &mut Vec::new(),
+        &mut HashSet::new(),
+        &mut failures,
+        &mut problems,
+    );
+    problems
+}
+
+/// Runs the level without any input, returns when the program exits if no token was placed by then
+fn finishes_idle(level: &Level, player: &Player) -> Option<f32> {
+    let mut level = level.clone();
+    let mut player = player.clone();
+    let code = level.code().to_owned();
+    let mut time = 0.0;
+    while time < IDLE_TIME && level.code() == code {
+        let frame_time = if level.lagging { 0.1 } else { FRAME_TIME };
+        time += frame_time;
+        if level.step(frame_time, &mut player) {
+            return (level.code() == code).then_some(time);
+        }
+        player.update(Input::default(), frame_time, &mut level);
+    }
+    None
+}
+
+/// Places the remaining tokens in every possible order and slot they fit, reporting orders that end
+/// with code that doesn't compile and no slot or token left to fix it
+fn try_orders<'a>(
+    level: &Level,
+    remaining: Vec<&'a Token>,
+    order: &mut Vec<String>,
+    visited: &mut HashSet<(String, Vec<&'a str>)>,
+    failures: &mut HashSet<String>,
+    problems: &mut Vec<String>,
+) {
+    let mut key = remaining
+        .iter()
+        .map(|token| token.token())
+        .collect::<Vec<_>>();
+    key.sort_unstable();
+    if !visited.insert((level.code().to_owned(), key)) {
+        return;
+    }
+
+    let mut placed_any = false;
+    for index in 0..remaining.len() {
+        let mut rest = remaining.clone();
+        let token = rest.remove(index);
+        for slot in 0..level.slot_count() {
+            if !token.kind().fits(level.slot_kind(slot)) {
+                continue;
+            }
+            let mut filled = level.clone();
+            if !filled.fill(slot, token) {
+                continue;
+            }
+            placed_any = true;
+            order.push(format!("`{}` into slot {}", token.token(), slot + 1));
+            try_orders(&filled, rest.clone(), order, visited, failures, problems);
+            order.pop();
+        }
+    }
+
+    if !placed_any {
+        if let CompilationStatus::Failure(error) = level.status() {
+            if failures.insert(level.code().to_owned()) {
+                problems.push(compile_error(
+                    level.code(),
+                    &error.message,
+                    error.offset,
+                    order,
+                ));
+            }
+        }
+    }
+}
+
+fn compile_error(code: &str, message: &str, offset: usize, order: &[String]) -> String {
+    let (line, column) = location(code, offset);
+    let placed = if order.is_empty() {
+        String::from("with no tokens placed")
+    } else {
+        format!("placing {}", order.join(", "))
+    };
+    format!(
+        "Compilation failed! {}: main.c:{}:{}: {}",
+        placed, line, column, message
+    )
+}
+
+/// `--validate [level numbers...]`: checks every level for slot and token consistency
+pub fn run(args: &[String]) -> Result<()> {
+    let levels = scan_levels().context("Failed to find levels!")?;
+    let mut broken = Vec::new();
+    for index in select_levels(args, &levels)? {
+        let name = format!("Level {} ({})", index + 1, levels[index].display());
+        let (level, player) = match Level::load(&levels, index) {
+            Result::Ok(loaded) => loaded.context("Level was removed")?,
+            Err(err) => {
+                println!("{}: {:#}", name, err);
+                broken.push(index + 1);
+                continue;
+            }
+        };
+
+        let problems = validate(&level, &player);
+        if problems.is_empty() {
+            println!("{}: ok", name);
+        } else {
+            println!("{}:", name);
+            for problem in &problems {
+                println!("  {}", problem);
+            }
+            broken.push(index + 1);
+        }
+    }
+
+    if !broken.is_empty() {
+        bail!("Invalid levels {:?}", broken);
+    }
+    Ok(())
+}